@@ -0,0 +1,43 @@
+// Compute shader: the ambient light each vertex receives from the sky
+
+[[block]]
+struct Sky {
+    top_color: vec3<f32>;
+    bottom_color: vec3<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> sky: Sky;
+
+[[block]]
+struct Params {
+    num_vertices: u32;
+};
+[[group(0), binding(1)]]
+var<uniform> params: Params;
+
+// The vertex buffer seen as raw floats, 8 per vertex: position (3), tex_coords (2) and normal (3)
+[[block]]
+struct Vertices {
+    data: [[stride(4)]] array<f32>;
+};
+[[group(0), binding(2)]]
+var<storage> vertices: [[access(read)]] Vertices;
+
+[[block]]
+struct AmbientLight {
+    colors: [[stride(16)]] array<vec4<f32>>;
+};
+[[group(0), binding(3)]]
+var<storage> ambient: [[access(read_write)]] AmbientLight;
+
+[[stage(compute), workgroup_size(64)]]
+fn main([[builtin(global_invocation_id)]] global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= params.num_vertices) {
+        return;
+    }
+    let normal_z = vertices.data[index * 8u + 7u];
+    // Faces looking up see the top of the sky, faces looking down only get the dimmer light bouncing off the ground
+    let t = vec3<f32>(normal_z * 0.5 + 0.5);
+    ambient.colors[index] = vec4<f32>(mix(sky.bottom_color * 0.5, sky.top_color, t), 1.0);
+}
@@ -3,18 +3,29 @@
 [[block]]
 struct Uniforms {
     view_proj: mat4x4<f32>;
+    inv_view_proj: mat4x4<f32>;
+    view_position: vec3<f32>;
+    fog_start: f32;
+    fog_color: vec3<f32>;
+    fog_end: f32;
+    time: f32;
+    ambient_shading: u32;
+    ambient_strength: f32;
+    diffuse_strength: f32;
+    specular_strength: f32;
+    shininess: f32;
 };
 [[group(0), binding(0)]]
 var<uniform> uniforms: Uniforms;
 
 struct VertexInput {
     [[location(0)]] position: vec3<f32>;
-    [[location(1)]] tex_coords: vec2<f32>;
+    [[location(1)]] color: vec3<f32>;
 };
 
 struct VertexOutput {
     [[builtin(position)]] clip_position: vec4<f32>;
-    [[location(0)]] tex_coords: vec2<f32>;
+    [[location(0)]] color: vec3<f32>;
 };
 
 [[stage(vertex)]]
@@ -22,8 +33,8 @@ fn main(
     in: VertexInput,
 ) -> VertexOutput {
     var out: VertexOutput;
-    out.tex_coords = in.tex_coords;
-    out.clip_position = vec4<f32>(in.position, 1.0);
+    out.color = in.color;
+    out.clip_position = uniforms.view_proj * vec4<f32>(in.position, 1.0);
     return out;
 }
 
@@ -31,5 +42,5 @@ fn main(
 
 [[stage(fragment)]]
 fn main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
-    return vec4<f32>(in.tex_coords , 0., 1.);
-}
\ No newline at end of file
+    return vec4<f32>(in.color, 1.0);
+}
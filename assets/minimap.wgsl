@@ -0,0 +1,54 @@
+// Vertex shader
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] tex_coords: vec2<f32>;
+};
+
+// A single triangle covering the whole viewport: (-1, -1), (3, -1) and (-1, 3)
+[[stage(vertex)]]
+fn main(
+    [[builtin(vertex_index)]] vertex_index: u32,
+) -> VertexOutput {
+    var out: VertexOutput;
+    let x = f32((vertex_index % 2u) * 4u) - 1.0;
+    let y = f32((vertex_index / 2u) * 4u) - 1.0;
+    out.clip_position = vec4<f32>(x, y, 0.0, 1.0);
+    // Texture coordinates go down while clip space goes up
+    out.tex_coords = vec2<f32>((x + 1.0) * 0.5, (1.0 - y) * 0.5);
+    return out;
+}
+
+// Fragment shader
+
+[[group(0), binding(0)]]
+var t_map: texture_2d<f32>;
+[[group(0), binding(1)]]
+var s_map: sampler;
+
+[[block]]
+struct Marker {
+    // Where the player faces, on the ground plane
+    facing: vec2<f32>;
+};
+[[group(0), binding(2)]]
+var<uniform> marker: Marker;
+
+[[stage(fragment)]]
+fn main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let map_color = textureSample(t_map, s_map, in.tex_coords);
+
+    // Position relative to the player, who is at the center. X goes right and Y up, like the world's X and Y.
+    let p = vec2<f32>(in.tex_coords.x * 2.0 - 1.0, 1.0 - in.tex_coords.y * 2.0);
+    let right = vec2<f32>(marker.facing.y, -marker.facing.x);
+    let along = dot(p, marker.facing);
+    let across = abs(dot(p, right));
+    // Arrowhead pointing where the player faces
+    if (along > -0.05 && along < 0.08 && across < (0.08 - along) * 0.5) {
+        return vec4<f32>(1.0, 0.2, 0.2, 1.0);
+    }
+    if (max(abs(p.x), abs(p.y)) > 0.97) {
+        return vec4<f32>(0.1, 0.1, 0.1, 1.0);
+    }
+    return map_color;
+}
@@ -0,0 +1,100 @@
+// Vertex shader
+
+[[block]]
+struct Uniforms {
+    view_proj: mat4x4<f32>;
+    inv_view_proj: mat4x4<f32>;
+    view_position: vec3<f32>;
+    fog_start: f32;
+    fog_color: vec3<f32>;
+    fog_end: f32;
+    time: f32;
+    ambient_shading: u32;
+    ambient_strength: f32;
+    diffuse_strength: f32;
+    specular_strength: f32;
+    shininess: f32;
+};
+[[group(1), binding(0)]]
+var<uniform> uniforms: Uniforms;
+
+[[block]]
+struct Light {
+    direction: vec3<f32>;
+    color: vec3<f32>;
+};
+[[group(1), binding(1)]]
+var<uniform> light: Light;
+
+// Computed by ambient.wgsl, one color per vertex
+[[block]]
+struct AmbientLight {
+    colors: [[stride(16)]] array<vec4<f32>>;
+};
+[[group(2), binding(0)]]
+var<storage> ambient: [[access(read)]] AmbientLight;
+
+struct VertexInput {
+    [[location(0)]] position: vec3<f32>;
+    [[location(1)]] tex_coords: vec2<f32>;
+    [[location(2)]] normal: vec3<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] tex_coords: vec2<f32>;
+    [[location(1)]] normal: vec3<f32>;
+    [[location(2)]] world_position: vec3<f32>;
+    [[location(3)]] ambient: vec3<f32>;
+};
+
+[[stage(vertex)]]
+fn main(
+    model: VertexInput,
+    [[builtin(vertex_index)]] vertex_index: u32,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.ambient = ambient.colors[vertex_index].rgb;
+    out.tex_coords = model.tex_coords;
+    out.normal = model.normal;
+    out.world_position = model.position;
+    out.clip_position = uniforms.view_proj * vec4<f32>(model.position, 1.0);
+    return out;
+}
+
+// Fragment shader
+
+[[group(0), binding(0)]]
+var t_diffuse: texture_2d<f32>;
+[[group(0), binding(1)]]
+var s_diffuse: sampler;
+
+[[stage(fragment)]]
+fn main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let object_color = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+
+    let normal = normalize(in.normal);
+    let diffuse = max(dot(normal, -light.direction), 0.0);
+    var shading: vec3<f32> = uniforms.ambient_strength * in.ambient + uniforms.diffuse_strength * diffuse * light.color;
+    if (uniforms.ambient_shading == 1u) {
+        // Cheap directional shading: bottom faces get darker and top faces lighter
+        shading = shading * (0.6 + 0.4 * (normal.z * 0.5 + 0.5));
+    }
+
+    // Blinn-Phong: the closer the normal is to halfway between the view and light directions, the brighter the highlight
+    let view_direction = normalize(uniforms.view_position - in.world_position);
+    let half_direction = normalize(view_direction - light.direction);
+    var specular: f32 = pow(max(dot(normal, half_direction), 0.0), uniforms.shininess);
+    if (diffuse <= 0.0) {
+        // Faces turned away from the light can't reflect it
+        specular = 0.0;
+    }
+    // Added on top of the texture color: highlights take the color of the light, not of the block
+    let highlight = uniforms.specular_strength * specular * light.color;
+
+    let distance_to_camera = distance(in.world_position, uniforms.view_position);
+    let fog = clamp((distance_to_camera - uniforms.fog_start) / (uniforms.fog_end - uniforms.fog_start), 0.0, 1.0);
+    let color = mix(object_color.rgb * shading + highlight, uniforms.fog_color, vec3<f32>(fog));
+
+    return vec4<f32>(color, object_color.a);
+}
\ No newline at end of file
@@ -0,0 +1,59 @@
+// Vertex shader
+
+[[block]]
+struct Uniforms {
+    view_proj: mat4x4<f32>;
+    inv_view_proj: mat4x4<f32>;
+    view_position: vec3<f32>;
+    fog_start: f32;
+    fog_color: vec3<f32>;
+    fog_end: f32;
+    time: f32;
+    ambient_shading: u32;
+    ambient_strength: f32;
+    diffuse_strength: f32;
+    specular_strength: f32;
+    shininess: f32;
+};
+[[group(0), binding(0)]]
+var<uniform> uniforms: Uniforms;
+
+[[block]]
+struct Sky {
+    top_color: vec3<f32>;
+    bottom_color: vec3<f32>;
+};
+[[group(0), binding(2)]]
+var<uniform> sky: Sky;
+
+struct VertexInput {
+    [[location(0)]] position: vec3<f32>;
+    [[location(1)]] tex_coords: vec2<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] ndc: vec2<f32>;
+};
+
+[[stage(vertex)]]
+fn main(
+    in: VertexInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.ndc = in.position.xy;
+    out.clip_position = vec4<f32>(in.position, 1.0);
+    return out;
+}
+
+// Fragment shader
+
+[[stage(fragment)]]
+fn main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    // Unproject the pixel on the near and far planes to get the direction it is looking at
+    let near = uniforms.inv_view_proj * vec4<f32>(in.ndc, 0.0, 1.0);
+    let far = uniforms.inv_view_proj * vec4<f32>(in.ndc, 1.0, 1.0);
+    let ray = normalize(far.xyz / far.w - near.xyz / near.w);
+    let t = vec3<f32>(clamp(ray.z, 0.0, 1.0));
+    return vec4<f32>(mix(sky.bottom_color, sky.top_color, t), 1.0);
+}
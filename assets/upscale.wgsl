@@ -0,0 +1,32 @@
+// Vertex shader
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] tex_coords: vec2<f32>;
+};
+
+// A single triangle covering the whole screen: (-1, -1), (3, -1) and (-1, 3)
+[[stage(vertex)]]
+fn main(
+    [[builtin(vertex_index)]] vertex_index: u32,
+) -> VertexOutput {
+    var out: VertexOutput;
+    let x = f32((vertex_index % 2u) * 4u) - 1.0;
+    let y = f32((vertex_index / 2u) * 4u) - 1.0;
+    out.clip_position = vec4<f32>(x, y, 0.0, 1.0);
+    // Texture coordinates go down while clip space goes up
+    out.tex_coords = vec2<f32>((x + 1.0) * 0.5, (1.0 - y) * 0.5);
+    return out;
+}
+
+// Fragment shader
+
+[[group(0), binding(0)]]
+var t_scene: texture_2d<f32>;
+[[group(0), binding(1)]]
+var s_scene: sampler;
+
+[[stage(fragment)]]
+fn main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    return textureSample(t_scene, s_scene, in.tex_coords);
+}
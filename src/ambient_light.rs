@@ -0,0 +1,142 @@
+use wgpu::util::DeviceExt;
+
+// Must match the workgroup_size of ambient.wgsl
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Computes on the GPU the ambient light each vertex of a mesh receives from the sky, into a storage buffer
+/// the vertex shader reads with the vertex index. It must run every frame because the sky follows the time of day.
+pub struct AmbientLight {
+    pipeline: wgpu::ComputePipeline,
+    compute_bind_group: wgpu::BindGroup,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    num_vertices: u32,
+}
+
+impl AmbientLight {
+    /// `vertex_buffer` must have been created with `BufferUsage::STORAGE`, and hold `Vertex`es
+    pub fn new(
+        device: &wgpu::Device,
+        vertex_buffer: &wgpu::Buffer,
+        num_vertices: u32,
+        sky_buffer: &wgpu::Buffer,
+        asset_source: &crate::assets::AssetSource,
+    ) -> Self {
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Ambient Light Params Buffer"),
+            // Padded to the 16 bytes of a uniform struct
+            contents: bytemuck::cast_slice(&[num_vertices, 0, 0, 0]),
+            usage: wgpu::BufferUsage::UNIFORM,
+        });
+        let ambient_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Ambient Light Buffer"),
+            // One vec4 per vertex
+            size: num_vertices.max(1) as wgpu::BufferAddress * 16,
+            usage: wgpu::BufferUsage::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let compute_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                buffer_layout_entry(0, wgpu::ShaderStage::COMPUTE, wgpu::BufferBindingType::Uniform),
+                buffer_layout_entry(1, wgpu::ShaderStage::COMPUTE, wgpu::BufferBindingType::Uniform),
+                buffer_layout_entry(2, wgpu::ShaderStage::COMPUTE, wgpu::BufferBindingType::Storage { read_only: true }),
+                buffer_layout_entry(3, wgpu::ShaderStage::COMPUTE, wgpu::BufferBindingType::Storage { read_only: false }),
+            ],
+            label: Some("ambient_light_compute_bind_group_layout"),
+        });
+        let compute_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &compute_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: sky_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: vertex_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: ambient_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("ambient_light_compute_bind_group"),
+        });
+
+        let shader = crate::render::create_wgsl_shader(device, "Ambient Light Shader", &asset_source.load_text("ambient.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Ambient Light Pipeline Layout"),
+            bind_group_layouts: &[&compute_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline =
+            crate::render::create_compute_pipeline(device, &pipeline_layout, &shader, "main", "Ambient Light Pipeline");
+
+        // What the render pipelines see: the result, read-only
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[buffer_layout_entry(
+                0,
+                wgpu::ShaderStage::VERTEX,
+                wgpu::BufferBindingType::Storage { read_only: true },
+            )],
+            label: Some("ambient_light_bind_group_layout"),
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: ambient_buffer.as_entire_binding(),
+            }],
+            label: Some("ambient_light_bind_group"),
+        });
+
+        Self {
+            pipeline,
+            compute_bind_group,
+            bind_group_layout,
+            bind_group,
+            num_vertices,
+        }
+    }
+
+    /// Layout of the group holding the ambient light of each vertex (binding 0), for the render pipelines that read it
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    /// Must be recorded before the render passes that read the result
+    pub fn compute(&self, encoder: &mut wgpu::CommandEncoder) {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Ambient Light"),
+        });
+        compute_pass.set_pipeline(&self.pipeline);
+        compute_pass.set_bind_group(0, &self.compute_bind_group, &[]);
+        compute_pass.dispatch((self.num_vertices + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE, 1, 1);
+    }
+}
+
+fn buffer_layout_entry(
+    binding: u32,
+    visibility: wgpu::ShaderStage,
+    ty: wgpu::BufferBindingType,
+) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility,
+        ty: wgpu::BindingType::Buffer {
+            ty,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
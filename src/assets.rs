@@ -0,0 +1,133 @@
+use std::borrow::Cow;
+use std::path::PathBuf;
+
+use anyhow::*;
+
+/// Extensions of the files `texture_names()` picks up
+const TEXTURE_EXTENSIONS: [&str; 3] = ["png", "jpg", "jpeg"];
+
+/// Every shader and image of the app, by file name. They all live in the assets directory.
+const EMBEDDED_ASSETS: &[(&str, &[u8])] = &[
+    ("shader.wgsl", include_bytes!("../assets/shader.wgsl")),
+    ("sky.wgsl", include_bytes!("../assets/sky.wgsl")),
+    ("line.wgsl", include_bytes!("../assets/line.wgsl")),
+    ("minimap.wgsl", include_bytes!("../assets/minimap.wgsl")),
+    ("upscale.wgsl", include_bytes!("../assets/upscale.wgsl")),
+    ("ambient.wgsl", include_bytes!("../assets/ambient.wgsl")),
+    ("happy-tree.png", include_bytes!("../assets/happy-tree.png")),
+];
+
+/// Where the shaders and images come from
+#[derive(Clone, Debug)]
+pub enum AssetSource {
+    /// Baked into the executable, so that it can be distributed on its own
+    Embedded,
+    /// Read from the files in this directory, so that they can be edited without recompiling (e.g. with shader reloading)
+    Directory(PathBuf),
+}
+
+/// Release builds embed everything. Debug builds read the files from the assets directory of the repository.
+impl Default for AssetSource {
+    fn default() -> Self {
+        if cfg!(debug_assertions) {
+            AssetSource::Directory(PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/assets")))
+        } else {
+            AssetSource::Embedded
+        }
+    }
+}
+
+impl AssetSource {
+    /// Never fails: if the file can't be read we log why and fall back to the embedded copy.
+    /// Panics if `name` is not one of the embedded assets.
+    pub fn load_bytes(&self, name: &str) -> Cow<'static, [u8]> {
+        self.try_load_bytes(name).unwrap_or_else(|err| panic!("{:#}", err))
+    }
+
+    /// Same as `load_bytes()`, but returns an error instead of panicking when the file can't be read and has no embedded copy
+    pub fn try_load_bytes(&self, name: &str) -> Result<Cow<'static, [u8]>> {
+        if let AssetSource::Directory(directory) = self {
+            let path = directory.join(name);
+            match std::fs::read(&path) {
+                Ok(bytes) => return Ok(Cow::Owned(bytes)),
+                Err(err) if embedded(name).is_some() => {
+                    log::warn!("Failed to read {}: {}. Using the embedded copy", path.display(), err)
+                }
+                Err(err) => return Err(err).with_context(|| format!("Failed to read {}", path.display())),
+            }
+        }
+        embedded(name)
+            .map(Cow::Borrowed)
+            .ok_or_else(|| anyhow!("{} is not an embedded asset", name))
+    }
+
+    /// Same as `load_bytes()`, for text files. Invalid UTF-8 also falls back to the embedded copy.
+    pub fn load_text(&self, name: &str) -> Cow<'static, str> {
+        match self.load_bytes(name) {
+            Cow::Borrowed(bytes) => Cow::Borrowed(std::str::from_utf8(bytes).expect("Embedded text assets are valid UTF-8")),
+            Cow::Owned(bytes) => match String::from_utf8(bytes) {
+                Ok(text) => Cow::Owned(text),
+                Err(err) => {
+                    log::warn!("{} is not valid UTF-8: {}. Using the embedded copy", name, err);
+                    let bytes = embedded(name).unwrap_or_else(|| panic!("{} is not an embedded asset", name));
+                    Cow::Borrowed(std::str::from_utf8(bytes).expect("Embedded text assets are valid UTF-8"))
+                }
+            },
+        }
+    }
+
+    /// Where the file is read from, or `None` if it is embedded
+    pub fn path(&self, name: &str) -> Option<PathBuf> {
+        match self {
+            AssetSource::Directory(directory) => Some(directory.join(name)),
+            AssetSource::Embedded => None,
+        }
+    }
+
+    /// Whether reloading would get anything different from what is already loaded
+    pub fn is_reloadable(&self) -> bool {
+        matches!(self, AssetSource::Directory(_))
+    }
+
+    /// Names of all the images that can be used as the cube texture, sorted, to pass to `try_load_bytes()`.
+    /// A directory can hold any number of them, to compare texture sets without recompiling.
+    pub fn texture_names(&self) -> Vec<String> {
+        let mut names = match self {
+            AssetSource::Directory(directory) => match std::fs::read_dir(directory) {
+                Ok(entries) => entries
+                    .filter_map(|entry| entry.ok())
+                    .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                    .filter(|name| is_texture(name))
+                    .collect(),
+                Err(err) => {
+                    log::warn!("Failed to list {}: {}. Using the embedded textures", directory.display(), err);
+                    Vec::new()
+                }
+            },
+            AssetSource::Embedded => Vec::new(),
+        };
+        if names.is_empty() {
+            names = EMBEDDED_ASSETS
+                .iter()
+                .map(|(name, _)| name.to_string())
+                .filter(|name| is_texture(name))
+                .collect();
+        }
+        names.sort();
+        names
+    }
+}
+
+fn embedded(name: &str) -> Option<&'static [u8]> {
+    EMBEDDED_ASSETS
+        .iter()
+        .find(|(asset_name, _)| *asset_name == name)
+        .map(|(_, bytes)| *bytes)
+}
+
+fn is_texture(name: &str) -> bool {
+    std::path::Path::new(name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map_or(false, |extension| TEXTURE_EXTENSIONS.contains(&extension.to_lowercase().as_str()))
+}
@@ -1,89 +1,341 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
 use winit::event::*;      
 
+use crate::settings::Settings;
+
+/// The world is Z-up: the ground is the XY plane, and yaw turns around this axis.
+/// Everything that needs "up" (the view matrix, vertical movement, the right direction) goes through it.
+pub const WORLD_UP: cgmath::Vector3<f32> = cgmath::Vector3::new(0., 0., 1.);
+
+#[derive(Copy, Clone, Debug)]
+pub enum ProjectionKind {
+      Perspective,
+      /// `height` is the number of world units visible vertically. The aspect ratio gives the horizontal extent.
+      Orthographic { height: f32 },
+}
+
+/// Which extent of the screen `Camera::fov` applies to.
+/// In `Horizontal` mode the horizontal field of view stays the same whatever the aspect ratio.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FovMode {
+      Vertical,
+      Horizontal,
+}
+
+/// What moving forward is relative to
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MovementBasis {
+      /// Along the ground, whatever the pitch: looking down doesn't make you sink
+      Ground,
+      /// Along `Camera::look_direction`: you fly towards where you aim
+      Look,
+}
+
+/// Where the camera starts, and which way it looks
+#[derive(Copy, Clone, Debug)]
+pub struct Spawn {
+      pub position: cgmath::Point3<f32>,
+      /// Angle around the Z axis, 0 looks towards +X
+      pub yaw: cgmath::Rad<f32>,
+      /// Angle above the horizon
+      pub pitch: cgmath::Rad<f32>,
+}
+
+impl Default for Spawn {
+      fn default() -> Self {
+            Self {
+                  position: (-10.0, 2.0, 1.0).into(),
+                  yaw: cgmath::Rad(0.),
+                  pitch: cgmath::Rad(0.),
+            }
+      }
+}
+
+#[derive(Clone)]
 pub struct Camera {
       position: cgmath::Point3<f32>,
       angle_ground: cgmath::Rad<f32>,
       angle_up: cgmath::Rad<f32>,
       pub aspect: f32,
-      fovy: f32,
+      pub projection: ProjectionKind,
+      /// In degrees
+      fov: f32,
+      fov_mode: FovMode,
+      /// Added to `fov`, for effects that temporarily widen or narrow the view. In degrees.
+      pub fov_offset: f32,
       znear: f32,
       zfar: f32,
 }
 
 impl Camera {
+      /// Starts at the default `Spawn`
+      #[allow(dead_code)]
       pub fn new(aspect: f32) -> Self {
+            let spawn = Spawn::default();
+            Self::with_transform(aspect, spawn.position, spawn.yaw, spawn.pitch)
+      }
+
+      /// The field of view, its mode and the clip planes are the ones of the default `Settings`
+      pub fn with_transform(aspect: f32, position: cgmath::Point3<f32>, yaw: cgmath::Rad<f32>, pitch: cgmath::Rad<f32>) -> Self {
+            let defaults = Settings::default();
             Self {
-                  position: (-10.0, 2.0, 1.0).into(),
-                  angle_ground: cgmath::Rad(0.),
-                  angle_up: cgmath::Rad(0.),
+                  position,
+                  angle_ground: yaw,
+                  angle_up: pitch,
                   aspect,
-                  fovy: 45.0,
-                  znear: 0.1,
-                  zfar: 100.0,
+                  projection: ProjectionKind::Perspective,
+                  fov: defaults.fov,
+                  fov_mode: defaults.fov_mode,
+                  fov_offset: 0.,
+                  znear: defaults.near_plane,
+                  zfar: defaults.render_distance,
             }
       }
 
       pub fn build_view_projection_matrix(&self) -> cgmath::Matrix4<f32> {
-            let view = cgmath::Matrix4::look_at_rh(self.position, self.position + self.look_direction(), cgmath::Vector3::unit_z());
-            let proj = cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar);
+            let view = cgmath::Matrix4::look_at_rh(self.position, self.position + self.look_direction(), WORLD_UP);
+            let proj = match self.projection {
+                  ProjectionKind::Perspective => cgmath::perspective(self.fovy(), self.aspect, self.znear, self.zfar),
+                  ProjectionKind::Orthographic { height } => {
+                        let half_height = height / 2.;
+                        let half_width = half_height * self.aspect;
+                        cgmath::ortho(-half_width, half_width, -half_height, half_height, self.znear, self.zfar)
+                  }
+            };
             proj * view
       }
 
-      fn look_direction(&self) -> cgmath::Vector3<f32> {
+      pub fn set_fov_mode(&mut self, fov_mode: FovMode) {
+            self.fov_mode = fov_mode;
+      }
+
+      /// In degrees, along the extent given by the `FovMode`
+      pub fn set_fov(&mut self, fov: f32) {
+            self.fov = fov.max(1.).min(179.);
+      }
+
+      /// The vertical field of view, which is what `cgmath::perspective` expects
+      fn fovy(&self) -> cgmath::Rad<f32> {
+            let fov = cgmath::Rad::from(cgmath::Deg((self.fov + self.fov_offset).max(1.).min(179.)));
+            match self.fov_mode {
+                  FovMode::Vertical => fov,
+                  FovMode::Horizontal => cgmath::Rad(2. * ((fov.0 / 2.).tan() / self.aspect).atan()),
+            }
+      }
+
+      /// The yaw and pitch that make the camera look at `target`
+      pub fn angles_towards(&self, target: cgmath::Point3<f32>) -> (cgmath::Rad<f32>, cgmath::Rad<f32>) {
+            let offset = target - self.position;
+            let yaw = cgmath::Rad(offset.y.atan2(offset.x));
+            let pitch = cgmath::Rad(offset.z.atan2((offset.x * offset.x + offset.y * offset.y).sqrt()));
+            (yaw, pitch)
+      }
+
+      pub fn toggle_projection(&mut self) {
+            self.projection = match self.projection {
+                  ProjectionKind::Perspective => ProjectionKind::Orthographic { height: 20. },
+                  ProjectionKind::Orthographic { .. } => ProjectionKind::Perspective,
+            };
+      }
+
+      pub fn position(&self) -> cgmath::Point3<f32> {
+            self.position
+      }
+
+      pub fn set_position(&mut self, position: cgmath::Point3<f32>) {
+            self.position = position;
+      }
+
+      pub fn zfar(&self) -> f32 {
+            self.zfar
+      }
+
+      /// The projection is rebuilt every frame from these, so the change applies on the next `update_view_proj`.
+      /// Depth precision mostly depends on the far / near ratio: keep `near` as large as the scene allows.
+      pub fn set_clip_planes(&mut self, near: f32, far: f32) {
+            if near <= 0. || far <= near {
+                  log::warn!("Ignoring invalid clip planes: near {}, far {}", near, far);
+                  return;
+            }
+            self.znear = near;
+            self.zfar = far;
+            log::info!("Clip planes: near {}, far {} (far / near ratio {:.0})", near, far, far / near);
+      }
+
+      /// Unit vector the camera is looking along. `WORLD_UP` is up, and the view matrix is built from it with `look_at_rh`.
+      pub fn look_direction(&self) -> cgmath::Vector3<f32> {
             use cgmath::Angle;
-            return (
+            (
                   Angle::cos(self.angle_up) * Angle::cos(self.angle_ground),
                   Angle::cos(self.angle_up) * Angle::sin(self.angle_ground),
                   Angle::sin(self.angle_up),
             ).into()
       }
 
-      fn forward_direction(&self) -> cgmath::Vector3<f32> {
+      /// `look_direction` projected on the ground (Z = 0) and normalized: where walking forward goes
+      pub fn forward_direction(&self) -> cgmath::Vector3<f32> {
             use cgmath::Angle;
-            return (
+            (
                   Angle::cos(self.angle_ground),
                   Angle::sin(self.angle_ground),
                   0.,
             ).into()
       }
 
-      fn right_direction(&self) -> cgmath::Vector3<f32> {
-            use cgmath::Angle;
-            return (
-                  Angle::sin(self.angle_ground),
-                  -Angle::cos(self.angle_ground),
-                  0.,
-            ).into()
+      /// `forward_direction` x `WORLD_UP`, which is the right of the screen in a right-handed world
+      pub fn right_direction(&self) -> cgmath::Vector3<f32> {
+            self.forward_direction().cross(WORLD_UP)
       }
 }
 
+/// Bounds for `CameraController::set_speed`, in units per second
+const MIN_SPEED: f32 = 0.6;
+const MAX_SPEED: f32 = 600.;
+/// How fast a fully tilted look stick turns the camera, in radians per second
+const LOOK_AXIS_SPEED: f32 = 2.5;
+
+/// A scripted turn of the camera, e.g. to look at something in a cutscene
+struct LookTransition {
+      from: (cgmath::Rad<f32>, cgmath::Rad<f32>),
+      to: (cgmath::Rad<f32>, cgmath::Rad<f32>),
+      /// In seconds
+      elapsed: f32,
+      duration: f32,
+}
+
 pub struct CameraController {
       speed: f32,
-      angle_ground_delta: cgmath::Rad<f32>,
-      angle_up_delta: cgmath::Rad<f32>,
-      is_up_pressed: bool,
-      is_down_pressed: bool,
-      is_forward_pressed: bool,
-      is_backward_pressed: bool,
-      is_left_pressed: bool,
-      is_right_pressed: bool,
+      /// How quickly the velocity catches up with the pressed keys, in 1/seconds. Higher is snappier.
+      pub acceleration: f32,
+      current_velocity: cgmath::Vector3<f32>,
+      /// Longest distance the camera can travel in a single frame, all physics steps together,
+      /// so that a frame time spike (e.g. after the window was unfocused) doesn't teleport it through things
+      pub max_step: f32,
+      /// Key that has to be held to sprint
+      pub sprint_key: VirtualKeyCode,
+      /// Multiplies the horizontal (X and Y) movement while sprinting. Vertical movement keeps its normal speed.
+      pub sprint_multiplier: f32,
+      is_sprinting: bool,
+      /// Degrees added to the field of view when moving at twice the normal speed (e.g. sprinting), for a sense of speed. 0 disables it.
+      pub speed_fov_boost: f32,
+      is_leveling_horizon: bool,
+      /// Whether forward follows the ground or the look direction
+      pub movement_basis: MovementBasis,
+      /// Analog movement (e.g. from a gamepad stick), added to the keys. X is right, Y is forward and Z is up, each in [-1, 1].
+      pub move_axis: cgmath::Vector3<f32>,
+      /// Analog look (e.g. from a gamepad stick). X turns right and Y looks up, each in [-1, 1].
+      pub look_axis: cgmath::Vector2<f32>,
+      /// Radians turned per pixel of mouse motion
+      pub mouse_sensitivity: f32,
+      /// When set, moving the mouse up looks down
+      invert_y: bool,
+      /// How long the mouse motion takes to be applied, in seconds. 0 turns instantly, higher values ease the rotation out.
+      pub look_smoothing: f32,
+      /// Mouse motion not applied to the camera yet, in pixels. X turns right and Y looks down.
+      look_input: cgmath::Vector2<f32>,
+      /// Movement from the keys, with the same axes as `move_axis`. Each component is -1, 0 or 1.
+      move_input: cgmath::Vector3<f32>,
+      /// Whether the negative and the positive key of each `move_input` axis are held
+      move_keys: [[bool; 2]; 3],
+      /// While set, the controller turns the camera and the player can't look around
+      look_transition: Option<LookTransition>,
 }
 
 impl CameraController {
+      /// The other tunables start at their default `Settings`
       pub fn new(speed: f32) -> Self {
+            let defaults = Settings::default();
             Self {
                   speed,
-                  angle_ground_delta: cgmath::Rad(0.),
-                  angle_up_delta: cgmath::Rad(0.),
-                  is_up_pressed: false,
-                  is_down_pressed: false,
-                  is_forward_pressed: false,
-                  is_backward_pressed: false,
-                  is_left_pressed: false,
-                  is_right_pressed: false,
+                  acceleration: 10.,
+                  current_velocity: cgmath::Vector3::new(0., 0., 0.),
+                  max_step: defaults.max_step,
+                  sprint_key: VirtualKeyCode::LControl,
+                  sprint_multiplier: 2.,
+                  is_sprinting: false,
+                  speed_fov_boost: defaults.speed_fov_boost,
+                  is_leveling_horizon: false,
+                  movement_basis: MovementBasis::Ground,
+                  move_axis: cgmath::Vector3::new(0., 0., 0.),
+                  look_axis: cgmath::Vector2::new(0., 0.),
+                  mouse_sensitivity: defaults.mouse_sensitivity,
+                  invert_y: defaults.invert_y,
+                  look_smoothing: defaults.look_smoothing,
+                  look_input: cgmath::Vector2::new(0., 0.),
+                  move_input: cgmath::Vector3::new(0., 0., 0.),
+                  move_keys: [[false; 2]; 3],
+                  look_transition: None,
             }
       }
 
+      /// Smoothly turns the camera to face `target` over `duration`, taking the shortest way around.
+      /// Mouse and stick look are disabled until it is done or `cancel_look_target` is called.
+      pub fn set_look_target(&mut self, camera: &Camera, target: cgmath::Point3<f32>, duration: Duration) {
+            use cgmath::Angle;
+            let (yaw, pitch) = camera.angles_towards(target);
+            // Go from the current yaw to the closest equivalent of the target yaw
+            let yaw = camera.angle_ground + (yaw - camera.angle_ground).normalize_signed();
+            self.look_transition = Some(LookTransition {
+                  from: (camera.angle_ground, camera.angle_up),
+                  to: (yaw, pitch),
+                  elapsed: 0.,
+                  duration: duration.as_secs_f32(),
+            });
+      }
+
+      /// Stops turning the camera where it currently is and gives the control back to the player
+      pub fn cancel_look_target(&mut self) {
+            self.look_transition = None;
+      }
+
+      /// Whether a `set_look_target` turn is still going on
+      pub fn has_look_target(&self) -> bool {
+            self.look_transition.is_some()
+      }
+
+      /// Updates the key of `axis` going towards `sign`. Holding both keys of an axis cancels out, like releasing both.
+      fn set_move_input(&mut self, axis: usize, sign: f32, is_pressed: bool) {
+            self.move_keys[axis][if sign > 0. { 1 } else { 0 }] = is_pressed;
+            let [negative, positive] = self.move_keys[axis];
+            self.move_input[axis] = positive as i32 as f32 - negative as i32 as f32;
+      }
+
+      /// Speed is in units per second, clamped to a sane range
+      pub fn set_speed(&mut self, speed: f32) {
+            let speed = speed.max(MIN_SPEED).min(MAX_SPEED);
+            if (speed - self.speed).abs() > f32::EPSILON {
+                  self.speed = speed;
+                  log::info!("Camera speed: {:.2}", self.speed);
+            }
+      }
+
+      /// Sprinting multiplies the horizontal speed by `sprint_multiplier`
+      pub fn set_sprinting(&mut self, is_sprinting: bool) {
+            self.is_sprinting = is_sprinting;
+      }
+
+      #[allow(dead_code)]
+      pub fn invert_y(&self) -> bool {
+            self.invert_y
+      }
+
+      pub fn set_invert_y(&mut self, invert_y: bool) {
+            self.invert_y = invert_y;
+      }
+
+      pub fn toggle_movement_basis(&mut self) {
+            self.movement_basis = match self.movement_basis {
+                  MovementBasis::Ground => MovementBasis::Look,
+                  MovementBasis::Look   => MovementBasis::Ground,
+            };
+            log::info!("Moving relative to the {}", match self.movement_basis {
+                  MovementBasis::Ground => "ground",
+                  MovementBasis::Look   => "look direction",
+            });
+      }
+
       pub fn process_events(&mut self, event: &WindowEvent) -> bool {
             match event {
                   WindowEvent::KeyboardInput {
@@ -91,6 +343,7 @@ impl CameraController {
                         KeyboardInput {
                               state,
                               scancode,
+                              virtual_keycode,
                               ..
                         },
                   ..
@@ -98,27 +351,49 @@ impl CameraController {
                   let is_pressed = *state == ElementState::Pressed;
                   match scancode {
                         57 /*space*/ => {
-                              self.is_up_pressed = is_pressed;
+                              self.set_move_input(2, 1., is_pressed);
                               true
                         }
                         42 /*shift*/ => {
-                              self.is_down_pressed = is_pressed;
+                              self.set_move_input(2, -1., is_pressed);
                               true
                         }
                         17 /*W*/ => {
-                              self.is_forward_pressed = is_pressed;
+                              self.set_move_input(1, 1., is_pressed);
                               true
                         }
                         30 /*A*/ => {
-                              self.is_left_pressed = is_pressed;
+                              self.set_move_input(0, -1., is_pressed);
                               true
                         }
                         31 /*S*/ => {
-                              self.is_backward_pressed = is_pressed;
+                              self.set_move_input(1, -1., is_pressed);
                               true
                         }
                         32 /*D*/ => {
-                              self.is_right_pressed = is_pressed;
+                              self.set_move_input(0, 1., is_pressed);
+                              true
+                        }
+                        19 /*R*/ => {
+                              if is_pressed {
+                                    self.is_leveling_horizon = true;
+                              }
+                              true
+                        }
+                        _ if matches!(virtual_keycode, Some(VirtualKeyCode::Equals) | Some(VirtualKeyCode::Plus) | Some(VirtualKeyCode::NumpadAdd)) => {
+                              if is_pressed {
+                                    self.set_speed(self.speed * 1.25);
+                              }
+                              true
+                        }
+                        _ if matches!(virtual_keycode, Some(VirtualKeyCode::Minus) | Some(VirtualKeyCode::NumpadSubtract)) => {
+                              if is_pressed {
+                                    self.set_speed(self.speed / 1.25);
+                              }
+                              true
+                        }
+                        _ if *virtual_keycode == Some(self.sprint_key) => {
+                              self.set_sprinting(is_pressed);
                               true
                         }
                         _ => false,
@@ -135,8 +410,8 @@ impl CameraController {
                         ..
                   } => {
                         if is_cursor_captured {
-                              self.angle_ground_delta -= cgmath::Rad(delta.0 as f32);
-                              self.angle_up_delta     -= cgmath::Rad(delta.1 as f32);
+                              let delta_up = if self.invert_y { -delta.1 } else { delta.1 };
+                              self.look_input += cgmath::Vector2::new(delta.0 as f32, delta_up as f32);
                               true
                         }
                         else {
@@ -147,23 +422,195 @@ impl CameraController {
             }
       }
 
-      pub fn update_camera(&mut self, camera: &mut Camera) {
+      /// The right, forward and up directions the movement input is applied along.
+      /// Up is always `WORLD_UP`: even when flying where we look, climbing doesn't depend on the pitch.
+      fn movement_axes(&self, camera: &Camera) -> [cgmath::Vector3<f32>; 3] {
+            let forward = match self.movement_basis {
+                  MovementBasis::Ground => camera.forward_direction(),
+                  MovementBasis::Look   => camera.look_direction(),
+            };
+            [camera.right_direction(), forward, WORLD_UP]
+      }
+
+      /// Moves the camera. Call it with a fixed `dt` so that movement doesn't depend on the frame rate.
+      /// Call `limit_frame_movement()` once all the steps of the frame are done.
+      pub fn update_movement(&mut self, camera: &mut Camera, dt: Duration) {
+            let dt = dt.as_secs_f32();
             const ZERO: cgmath::Vector3<f32> = cgmath::Vector3{x: 0., y: 0., z: 0.};
-            let direction =
-                  if self.is_forward_pressed  {  camera.forward_direction() } else { ZERO } +
-                  if self.is_backward_pressed { -camera.forward_direction() } else { ZERO } +
-                  if self.is_right_pressed    {  camera.right_direction  () } else { ZERO } +
-                  if self.is_left_pressed     { -camera.right_direction  () } else { ZERO } +
-                  if self.is_up_pressed       {  cgmath::Vector3::unit_z () } else { ZERO } +
-                  if self.is_down_pressed     { -cgmath::Vector3::unit_z () } else { ZERO }
-            ;
+            let input = self.move_input + self.move_axis;
+            let [right, forward, up] = self.movement_axes(camera);
+            let direction = right * input.x + forward * input.y + up * input.z;
             let magnitude = cgmath::InnerSpace::magnitude(direction);
-            if magnitude > 0.001 {
-                  camera.position += direction / magnitude * self.speed;
+            // Only shorten the direction when it is too long, so that a half-tilted stick moves at half speed
+            let mut target_velocity = if magnitude > 0.001 { direction / magnitude.max(1.) * self.speed } else { ZERO };
+            if self.is_sprinting {
+                  target_velocity.x *= self.sprint_multiplier;
+                  target_velocity.y *= self.sprint_multiplier;
+            }
+            self.current_velocity += (target_velocity - self.current_velocity) * (self.acceleration * dt).min(1.);
+            if cgmath::InnerSpace::magnitude(self.current_velocity) > 0.001 {
+                  camera.position += self.current_velocity * dt;
+            }
+            else {
+                  self.current_velocity = ZERO;
+            }
+      }
+
+      /// Brings the camera back within `max_step` of where it was at the start of the frame.
+      /// Returns whether it had gone further.
+      pub fn limit_frame_movement(&self, camera: &mut Camera, frame_start: cgmath::Point3<f32>) -> bool {
+            let displacement = camera.position - frame_start;
+            let distance = cgmath::InnerSpace::magnitude(displacement);
+            if distance > self.max_step {
+                  camera.position = frame_start + displacement * (self.max_step / distance);
+                  true
+            }
+            else {
+                  false
+            }
+      }
+
+      /// Turns the camera and updates the speed effects. Call it once per frame, after `update_movement()`, with the frame's `dt`.
+      pub fn update_look(&mut self, camera: &mut Camera, dt: Duration) {
+            let dt = dt.as_secs_f32();
+            // Widen the view as we go faster than the normal speed, and ease back when slowing down
+            const MAX_FOV_BOOST: f32 = 30.;
+            let speed_ratio = cgmath::InnerSpace::magnitude(self.current_velocity) / self.speed;
+            let target_fov_offset = (self.speed_fov_boost * (speed_ratio - 1.).max(0.)).min(MAX_FOV_BOOST);
+            camera.fov_offset += (target_fov_offset - camera.fov_offset) * (5. * dt).min(1.);
+            if let Some(transition) = &mut self.look_transition {
+                  transition.elapsed += dt;
+                  let t = if transition.duration > 0. { (transition.elapsed / transition.duration).min(1.) } else { 1. };
+                  // Ease in and out
+                  let t = t * t * (3. - 2. * t);
+                  camera.angle_ground = transition.from.0 + (transition.to.0 - transition.from.0) * t;
+                  camera.angle_up     = transition.from.1 + (transition.to.1 - transition.from.1) * t;
+                  if transition.elapsed >= transition.duration {
+                        self.look_transition = None;
+                  }
+                  // The player's look input is dropped rather than applied once the transition ends
+                  self.look_input = cgmath::Vector2::new(0., 0.);
+                  return;
+            }
+            // Apply part of the pending mouse motion and keep the rest for the next frames.
+            // Everything is eventually applied, so smoothing never makes the camera drift.
+            let applied = if self.look_smoothing > 0. { 1. - (-dt / self.look_smoothing).exp() } else { 1. };
+            camera.angle_ground -= cgmath::Rad(self.look_input.x * applied * self.mouse_sensitivity);
+            camera.angle_up     -= cgmath::Rad(self.look_input.y * applied * self.mouse_sensitivity);
+            camera.angle_ground -= cgmath::Rad(self.look_axis.x * LOOK_AXIS_SPEED * dt);
+            camera.angle_up     += cgmath::Rad(self.look_axis.y * LOOK_AXIS_SPEED * dt);
+            if self.is_leveling_horizon {
+                  // Exponential ease towards a level horizon, mostly done after a third of a second
+                  const LEVELING_SPEED: f32 = 10.;
+                  camera.angle_up *= 1. - (LEVELING_SPEED * dt).min(1.);
+                  if camera.angle_up.0.abs() < 0.001 {
+                        camera.angle_up = cgmath::Rad(0.);
+                        self.is_leveling_horizon = false;
+                  }
+            }
+            self.look_input *= 1. - applied;
+            // Less than a hundredth of a pixel left: consider the motion done
+            if self.look_input.x.abs() < 0.01 {
+                  self.look_input.x = 0.;
+            }
+            if self.look_input.y.abs() < 0.01 {
+                  self.look_input.y = 0.;
+            }
+      }
+}
+
+#[cfg(test)]
+mod tests {
+      use super::*;
+
+      fn assert_close(actual: f32, expected: f32) {
+            assert!((actual - expected).abs() < 1e-5, "{} is not {}", actual, expected);
+      }
+
+      #[test]
+      fn opposite_keys_cancel_out() {
+            let mut controller = CameraController::new(1.);
+            controller.set_move_input(1, 1., true);
+            controller.set_move_input(1, -1., true);
+            assert_close(controller.move_input.y, 0.);
+            // Releasing one of them goes back to the one still held
+            controller.set_move_input(1, 1., false);
+            assert_close(controller.move_input.y, -1.);
+            controller.set_move_input(1, -1., false);
+            assert_close(controller.move_input.y, 0.);
+      }
+
+      fn assert_orthonormal_pair(a: cgmath::Vector3<f32>, b: cgmath::Vector3<f32>) {
+            use cgmath::InnerSpace;
+            assert_close(a.magnitude(), 1.);
+            assert_close(b.magnitude(), 1.);
+            assert_close(a.dot(b), 0.);
+      }
+
+      /// Cameras all around, including straight up and straight down
+      fn cameras() -> Vec<Camera> {
+            let mut cameras = Vec::new();
+            for &yaw in [0., 1., -2.5, std::f32::consts::PI].iter() {
+                  for &pitch in [0., 0.7, -1.2, std::f32::consts::FRAC_PI_2, -std::f32::consts::FRAC_PI_2].iter() {
+                        cameras.push(Camera::with_transform(1., (1., 2., 3.).into(), cgmath::Rad(yaw), cgmath::Rad(pitch)));
+                  }
+            }
+            cameras
+      }
+
+      #[test]
+      fn ground_basis_is_orthonormal_and_right_handed() {
+            use cgmath::InnerSpace;
+            let controller = CameraController::new(1.);
+            assert_eq!(controller.movement_basis, MovementBasis::Ground);
+            for camera in cameras() {
+                  let [right, forward, up] = controller.movement_axes(&camera);
+                  assert_orthonormal_pair(right, forward);
+                  assert_orthonormal_pair(forward, up);
+                  assert_orthonormal_pair(up, right);
+                  assert_close((right.cross(forward) - up).magnitude(), 0.);
             }
-            camera.angle_ground += self.angle_ground_delta * 0.001;
-            camera.angle_up     += self.angle_up_delta     * 0.001; 
-            self.angle_ground_delta = cgmath::Rad(0.);
-            self.angle_up_delta = cgmath::Rad(0.);
+      }
+
+      #[test]
+      fn look_basis_is_orthonormal_and_right_handed() {
+            use cgmath::InnerSpace;
+            let mut controller = CameraController::new(1.);
+            controller.toggle_movement_basis();
+            assert_eq!(controller.movement_basis, MovementBasis::Look);
+            for camera in cameras() {
+                  let [right, forward, _] = controller.movement_axes(&camera);
+                  assert_close((forward - camera.look_direction()).magnitude(), 0.);
+                  // Right stays horizontal, even when looking straight up or down
+                  assert_close(right.z, 0.);
+                  assert_orthonormal_pair(right, forward);
+                  // The up of the screen completes the frame: it never points below the horizon
+                  let screen_up = right.cross(forward);
+                  assert_close(screen_up.magnitude(), 1.);
+                  assert!(screen_up.dot(WORLD_UP) >= -1e-5);
+            }
+      }
+
+      #[test]
+      fn look_target_ends_facing_the_target() {
+            use cgmath::InnerSpace;
+            let mut controller = CameraController::new(1.);
+            let mut camera = Camera::new(1.);
+            let target = cgmath::Point3::new(3., -4., 6.);
+            controller.set_look_target(&camera, target, Duration::from_millis(500));
+            controller.update_look(&mut camera, Duration::from_millis(300));
+            assert!(controller.has_look_target());
+            controller.update_look(&mut camera, Duration::from_millis(300));
+            assert!(!controller.has_look_target());
+            let expected = (target - camera.position()).normalize();
+            assert_close((camera.look_direction() - expected).magnitude(), 0.);
+      }
+
+      #[test]
+      fn releasing_a_key_not_held_changes_nothing() {
+            let mut controller = CameraController::new(1.);
+            controller.set_move_input(0, 1., true);
+            controller.set_move_input(0, -1., false);
+            assert_close(controller.move_input.x, 1.);
       }
 }
\ No newline at end of file
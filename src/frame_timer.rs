@@ -0,0 +1,68 @@
+use std::time::{Duration, Instant};
+
+/// Measures the time between frames and logs FPS / frametime statistics once per second
+pub struct FrameTimer {
+    last_frame: Instant,
+    report_start: Instant,
+    frame_count: u32,
+    total_frametime: Duration,
+    min_frametime: Duration,
+    max_frametime: Duration,
+    fps: f32,
+}
+
+impl FrameTimer {
+    pub fn new() -> Self {
+        let now = Instant::now();
+        Self {
+            last_frame: now,
+            report_start: now,
+            frame_count: 0,
+            total_frametime: Duration::ZERO,
+            min_frametime: Duration::MAX,
+            max_frametime: Duration::ZERO,
+            fps: 0.,
+        }
+    }
+
+    /// Must be called once per frame. Returns the time elapsed since the previous call.
+    pub fn tick(&mut self) -> Duration {
+        let now = Instant::now();
+        let dt = now - self.last_frame;
+        self.last_frame = now;
+
+        self.frame_count += 1;
+        self.total_frametime += dt;
+        self.min_frametime = self.min_frametime.min(dt);
+        self.max_frametime = self.max_frametime.max(dt);
+
+        let elapsed = now - self.report_start;
+        if elapsed >= Duration::from_secs(1) {
+            self.fps = self.frame_count as f32 / elapsed.as_secs_f32();
+            log::info!(
+                "{:.1} FPS | frametime min {:.2}ms, max {:.2}ms, avg {:.2}ms",
+                self.fps,
+                self.min_frametime.as_secs_f32() * 1000.,
+                self.max_frametime.as_secs_f32() * 1000.,
+                self.total_frametime.as_secs_f32() * 1000. / self.frame_count as f32,
+            );
+            self.report_start = now;
+            self.frame_count = 0;
+            self.total_frametime = Duration::ZERO;
+            self.min_frametime = Duration::MAX;
+            self.max_frametime = Duration::ZERO;
+        }
+
+        dt
+    }
+
+    /// Time elapsed since the last call to `tick()`
+    pub fn time_since_last_frame(&self) -> Duration {
+        self.last_frame.elapsed()
+    }
+
+    /// Average FPS over the last full second
+    pub fn fps(&self) -> f32 {
+        self.fps
+    }
+}
@@ -0,0 +1,63 @@
+use gilrs::{Axis, Button, Gilrs};
+
+use crate::camera::CameraController;
+
+/// Stick values below this are considered to be noise and ignored
+const DEADZONE: f32 = 0.15;
+
+/// Feeds the first connected gamepad into the camera controller:
+/// left stick moves, right stick looks around, South / East buttons go up / down.
+pub struct GamepadInput {
+    // None if gamepads aren't supported on this platform
+    gilrs: Option<Gilrs>,
+}
+
+impl GamepadInput {
+    pub fn new() -> Self {
+        let gilrs = match Gilrs::new() {
+            Ok(gilrs) => Some(gilrs),
+            Err(err) => {
+                log::warn!("Gamepad support disabled: {}", err);
+                None
+            }
+        };
+        Self { gilrs }
+    }
+
+    pub fn update(&mut self, controller: &mut CameraController) {
+        let gilrs = match &mut self.gilrs {
+            Some(gilrs) => gilrs,
+            None => return,
+        };
+        // Gilrs only updates the gamepads' state when processing their events
+        while gilrs.next_event().is_some() {}
+
+        match gilrs.gamepads().next() {
+            Some((_, gamepad)) => {
+                let button = |button| if gamepad.is_pressed(button) { 1. } else { 0. };
+                controller.move_axis = cgmath::Vector3::new(
+                    apply_deadzone(gamepad.value(Axis::LeftStickX)),
+                    apply_deadzone(gamepad.value(Axis::LeftStickY)),
+                    button(Button::South) - button(Button::East),
+                );
+                controller.look_axis = cgmath::Vector2::new(
+                    apply_deadzone(gamepad.value(Axis::RightStickX)),
+                    apply_deadzone(gamepad.value(Axis::RightStickY)),
+                );
+            }
+            None => {
+                controller.move_axis = cgmath::Vector3::new(0., 0., 0.);
+                controller.look_axis = cgmath::Vector2::new(0., 0.);
+            }
+        }
+    }
+}
+
+/// Zeroes small values and rescales the rest so that the output still goes smoothly from 0 to 1
+fn apply_deadzone(value: f32) -> f32 {
+    if value.abs() < DEADZONE {
+        0.
+    } else {
+        value.signum() * (value.abs() - DEADZONE) / (1. - DEADZONE)
+    }
+}
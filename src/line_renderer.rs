@@ -0,0 +1,193 @@
+use wgpu::util::DeviceExt;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct LineVertex {
+    position: [f32; 3],
+    color: [f32; 3],
+}
+
+impl LineVertex {
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        use std::mem;
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<LineVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::InputStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+            ],
+        }
+    }
+}
+
+/// Draws colored segments on top of the scene, for debugging.
+/// Segments are queued with `push()` during the frame, uploaded with `upload()` and forgotten afterwards.
+pub struct LineRenderer {
+    shader: wgpu::ShaderModule,
+    pipeline_layout: wgpu::PipelineLayout,
+    pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    // Number of vertices the buffer can hold
+    capacity: usize,
+    num_vertices: u32,
+    segments: Vec<(cgmath::Point3<f32>, cgmath::Point3<f32>, [f32; 3])>,
+}
+
+impl LineRenderer {
+    /// `uniform_bind_group_layout` must be the layout of the bind group that is passed to `draw()`
+    pub fn new(
+        device: &wgpu::Device,
+        uniform_bind_group_layout: &wgpu::BindGroupLayout,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        asset_source: &crate::assets::AssetSource,
+    ) -> Self {
+        let shader = crate::render::create_wgsl_shader(device, "Line Shader", &asset_source.load_text("line.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Line Pipeline Layout"),
+            bind_group_layouts: &[uniform_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = create_line_pipeline(device, &pipeline_layout, &shader, format, sample_count);
+        let capacity = 256;
+        let vertex_buffer = create_vertex_buffer(device, capacity);
+        Self {
+            shader,
+            pipeline_layout,
+            pipeline,
+            vertex_buffer,
+            capacity,
+            num_vertices: 0,
+            segments: Vec::new(),
+        }
+    }
+
+    /// Must be called whenever the sample count of the render target changes
+    pub fn set_sample_count(&mut self, device: &wgpu::Device, format: wgpu::TextureFormat, sample_count: u32) {
+        self.pipeline = create_line_pipeline(device, &self.pipeline_layout, &self.shader, format, sample_count);
+    }
+
+    /// Queues a segment for the current frame
+    pub fn push(&mut self, a: cgmath::Point3<f32>, b: cgmath::Point3<f32>, color: [f32; 3]) {
+        self.segments.push((a, b, color));
+    }
+
+    /// Queues the 12 edges of an axis-aligned box
+    pub fn push_aabb(&mut self, min: cgmath::Point3<f32>, max: cgmath::Point3<f32>, color: [f32; 3]) {
+        let corner = |x: bool, y: bool, z: bool| {
+            cgmath::Point3::new(
+                if x { max.x } else { min.x },
+                if y { max.y } else { min.y },
+                if z { max.z } else { min.z },
+            )
+        };
+        for &a in &[false, true] {
+            for &b in &[false, true] {
+                self.push(corner(false, a, b), corner(true, a, b), color);
+                self.push(corner(a, false, b), corner(a, true, b), color);
+                self.push(corner(a, b, false), corner(a, b, true), color);
+            }
+        }
+    }
+
+    /// Queues the X, Y and Z axes starting at `origin`, in red, green and blue
+    pub fn push_axes(&mut self, origin: cgmath::Point3<f32>, length: f32) {
+        self.push(origin, origin + cgmath::Vector3::unit_x() * length, [1., 0., 0.]);
+        self.push(origin, origin + cgmath::Vector3::unit_y() * length, [0., 1., 0.]);
+        self.push(origin, origin + cgmath::Vector3::unit_z() * length, [0., 0., 1.]);
+    }
+
+    /// Sends the queued segments to the GPU and clears the queue, ready for the next frame
+    pub fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let vertices: Vec<LineVertex> = self
+            .segments
+            .drain(..)
+            .flat_map(|(a, b, color)| {
+                std::iter::once(LineVertex { position: a.into(), color })
+                    .chain(std::iter::once(LineVertex { position: b.into(), color }))
+            })
+            .collect();
+        if vertices.len() > self.capacity {
+            self.capacity = vertices.len().next_power_of_two();
+            self.vertex_buffer = create_vertex_buffer(device, self.capacity);
+        }
+        if !vertices.is_empty() {
+            queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+        }
+        self.num_vertices = vertices.len() as u32;
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, uniform_bind_group: &'a wgpu::BindGroup) {
+        if self.num_vertices == 0 {
+            return;
+        }
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, uniform_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.draw(0..self.num_vertices, 0..1);
+    }
+}
+
+fn create_vertex_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+    let vertices = vec![LineVertex { position: [0.; 3], color: [0.; 3] }; capacity];
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Line Vertex Buffer"),
+        contents: bytemuck::cast_slice(&vertices),
+        usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+    })
+}
+
+fn create_line_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Line Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "main",
+            buffers: &[LineVertex::desc()],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "main",
+            targets: &[wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState {
+                    color: wgpu::BlendComponent::REPLACE,
+                    alpha: wgpu::BlendComponent::REPLACE,
+                }),
+                write_mask: wgpu::ColorWrite::ALL,
+            }],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::LineList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            // Lines have no faces to cull
+            cull_mode: None,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            clamp_depth: false,
+            conservative: false,
+        },
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+    })
+}
@@ -1,20 +1,34 @@
+use std::collections::HashSet;
 use std::iter;
+use std::sync::{Arc, Mutex};
 
 use wgpu::util::DeviceExt;
 use winit::{
     event::*,
     event_loop::{ControlFlow, EventLoop},
-    window::{Window, WindowBuilder},
+    window::{Icon, Window, WindowBuilder},
 };
 
 mod texture;
-mod camera; use camera::*;
+mod ambient_light; use ambient_light::AmbientLight;
+mod assets; use assets::AssetSource;
+mod camera; use camera::{Camera, CameraController, Spawn};
+mod frame_timer; use frame_timer::FrameTimer;
+mod gamepad; use gamepad::GamepadInput;
+mod line_renderer; use line_renderer::LineRenderer;
+mod mesh;
+mod minimap; use minimap::Minimap;
+mod settings; use settings::Settings;
+mod time_of_day;
+mod upscaler; use upscaler::Upscaler;
+mod render;
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 struct Vertex {
     position: [f32; 3],
     tex_coords: [f32; 2],
+    normal: [f32; 3],
 }
 
 impl Vertex {
@@ -34,142 +48,36 @@ impl Vertex {
                     shader_location: 1,
                     format: wgpu::VertexFormat::Float32x2,
                 },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 5]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
             ],
         }
     }
 }
 
-const VERTICES: &[Vertex] = &[
-    // Face Front
-    Vertex {
-        position: [1., 1., 1.],
-        tex_coords: [1., 0.],
-    },
-    Vertex {
-        position: [1., -1., 1.],
-        tex_coords: [1., 1.],
-    },
-    Vertex {
-        position: [1., -1., -1.],
-        tex_coords: [0., 1.],
-    },
-    Vertex {
-        position: [1., 1., -1.],
-        tex_coords: [0., 0.],
-    },
-    // Face Back
-    Vertex {
-        position: [-1., 1., 1.],
-        tex_coords: [1., 0.],
-    },
-    Vertex {
-        position: [-1., -1., 1.],
-        tex_coords: [1., 1.],
-    },
-    Vertex {
-        position: [-1., -1., -1.],
-        tex_coords: [0., 1.],
-    },
-    Vertex {
-        position: [-1., 1., -1.],
-        tex_coords: [0., 0.],
-    },
-    // Face Left
-    Vertex {
-        position: [1., -1., 1.],
-        tex_coords: [1., 0.],
-    },
-    Vertex {
-        position: [-1., -1., 1.],
-        tex_coords: [1., 1.],
-    },
-    Vertex {
-        position: [-1., -1., -1.],
-        tex_coords: [0., 1.],
-    },
-    Vertex {
-        position: [1., -1., -1.],
-        tex_coords: [0., 0.],
-    },
-    // Face Right
-    Vertex {
-        position: [1., 1., 1.],
-        tex_coords: [1., 0.],
-    },
-    Vertex {
-        position: [-1., 1., 1.],
-        tex_coords: [1., 1.],
-    },
-    Vertex {
-        position: [-1., 1., -1.],
-        tex_coords: [0., 1.],
-    },
-    Vertex {
-        position: [1., 1., -1.],
-        tex_coords: [0., 0.],
-    },
-    // Face Up
-    Vertex {
-        position: [1., 1., 1.],
-        tex_coords: [1., 0.],
-    },
-    Vertex {
-        position: [-1., 1., 1.],
-        tex_coords: [1., 1.],
-    },
-    Vertex {
-        position: [-1., -1., 1.],
-        tex_coords: [0., 1.],
-    },
-    Vertex {
-        position: [1., -1., 1.],
-        tex_coords: [0., 0.],
-    },
-    // Face Down
-    Vertex {
-        position: [1., 1., -1.],
-        tex_coords: [1., 0.],
-    },
-    Vertex {
-        position: [-1., 1., -1.],
-        tex_coords: [1., 1.],
-    },
-    Vertex {
-        position: [-1., -1., -1.],
-        tex_coords: [0., 1.],
-    },
-    Vertex {
-        position: [1., -1., -1.],
-        tex_coords: [0., 0.],
-    },
-];
-
-#[rustfmt::skip]
-const INDICES: &[u16] = &[
-    0, 1, 2, 0, 2, 3,
-    7, 6, 4, 6, 5, 4,
-    8, 9, 10, 8, 10, 11,
-    15, 14, 12, 14, 13, 12,
-    16, 17, 18, 16, 18, 19,
-    23, 22, 20, 22, 21, 20,
-];
-
 const SKY_VERTICES: &[Vertex] = &[
     Vertex {
         position: [-1., -1., 0.],
         tex_coords: [0., 0.],
+        normal: [0., 0., 1.],
     },
     Vertex {
         position: [1., -1., 0.],
         tex_coords: [1., 0.],
+        normal: [0., 0., 1.],
     },
     Vertex {
         position: [1., 1., 0.],
         tex_coords: [1., 1.],
+        normal: [0., 0., 1.],
     },
     Vertex {
         position: [-1., 1., 0.],
         tex_coords: [0., 1.],
+        normal: [0., 0., 1.],
     },
 ];
 
@@ -178,6 +86,9 @@ const SKY_INDICES: &[u16] = &[
     0, 1, 2, 0, 2, 3,
 ];
 
+// cgmath builds OpenGL style projections, which map the view depth between near and far to [-1, 1].
+// wgpu expects [0, 1], so this remaps Z with z' = z * 0.5 + 0.5. Near still ends up at 0 and far at 1,
+// so anything comparing depths (e.g. a future depth buffer) keeps the usual "smaller is closer" convention.
 #[rustfmt::skip]
 pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
     1.0, 0.0, 0.0, 0.0,
@@ -186,10 +97,32 @@ pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
     0.0, 0.0, 0.5, 1.0,
 );
 
+/// Mirrors the Uniforms struct of the shaders.
+/// Each vec3 must be followed by a scalar (or padding) to fill its 16 byte slot, as WGSL would.
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 struct Uniforms {
     view_proj: [[f32; 4]; 4],
+    // Used by the sky to reconstruct the view ray of each pixel
+    inv_view_proj: [[f32; 4]; 4],
+    view_position: [f32; 3],
+    // Distance at which the fog starts to appear
+    fog_start: f32,
+    fog_color: [f32; 3],
+    // Distance at which everything is fully hidden by the fog
+    fog_end: f32,
+    // Seconds spent playing, for animations. It stops while paused.
+    time: f32,
+    // 1 to darken faces looking down and lighten faces looking up, 0 to disable it. WGSL has no bool in uniforms.
+    ambient_shading: u32,
+    // How much of the sky's ambient light and of the sun's direct light the blocks receive
+    ambient_strength: f32,
+    diffuse_strength: f32,
+    // Blinn-Phong highlights: how bright they are, and how tight (higher is shinier)
+    specular_strength: f32,
+    shininess: f32,
+    // The struct's size must be a multiple of 16 bytes, like WGSL's
+    _padding: [f32; 2],
 }
 
 impl Uniforms {
@@ -197,126 +130,442 @@ impl Uniforms {
         use cgmath::SquareMatrix;
         Self {
             view_proj: cgmath::Matrix4::identity().into(),
+            inv_view_proj: cgmath::Matrix4::identity().into(),
+            view_position: [0.; 3],
+            fog_start: 0.,
+            fog_color: [0.; 3],
+            fog_end: 0.,
+            time: 0.,
+            ambient_shading: 1,
+            ambient_strength: 0.25,
+            diffuse_strength: 1.,
+            specular_strength: 0.3,
+            shininess: 32.,
+            _padding: [0.; 2],
         }
     }
 
     fn update_view_proj(&mut self, camera: &Camera) {
-        self.view_proj = (OPENGL_TO_WGPU_MATRIX * camera.build_view_projection_matrix()).into();
+        use cgmath::SquareMatrix;
+        let view_proj = OPENGL_TO_WGPU_MATRIX * camera.build_view_projection_matrix();
+        self.view_proj = view_proj.into();
+        if let Some(inv_view_proj) = view_proj.invert() {
+            self.inv_view_proj = inv_view_proj.into();
+        }
+        self.view_position = camera.position().into();
+    }
+
+    fn set_fog(&mut self, color: [f32; 3], start: f32, end: f32) {
+        self.fog_color = color;
+        self.fog_start = start;
+        self.fog_end = end;
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct Light {
+    direction: [f32; 3],
+    // Uniforms require 16 byte (4 float) spacing, so we need padding after each vec3
+    _padding: u32,
+    color: [f32; 3],
+    _padding2: u32,
+}
+
+impl Light {
+    fn new(direction: cgmath::Vector3<f32>, color: [f32; 3]) -> Self {
+        use cgmath::InnerSpace;
+        Self {
+            direction: direction.normalize().into(),
+            _padding: 0,
+            color,
+            _padding2: 0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct Sky {
+    // Color straight up
+    top_color: [f32; 3],
+    _padding: u32,
+    // Color at (and below) the horizon
+    bottom_color: [f32; 3],
+    _padding2: u32,
+}
+
+impl Sky {
+    fn new(top_color: [f32; 3], bottom_color: [f32; 3]) -> Self {
+        Self {
+            top_color,
+            _padding: 0,
+            bottom_color,
+            _padding2: 0,
+        }
     }
 }
 
+// WGSL lays uniform structs out in 16 byte slots, and rounds their size up to a multiple of 16.
+// These don't compile (mismatched array lengths) if one of our structs stops matching that, e.g. after adding a field without padding.
+const _: [(); 0] = [(); std::mem::size_of::<Uniforms>() % 16];
+const _: [(); 0] = [(); std::mem::size_of::<Light>() % 16];
+const _: [(); 0] = [(); std::mem::size_of::<Sky>() % 16];
+
+/// The backend forced with the MYCRAFT_BACKEND environment variable (vulkan, metal, dx12, dx11 or gl), if any.
+/// Handy to check whether a rendering issue is specific to one backend.
+fn backend_from_env() -> Option<wgpu::BackendBit> {
+    let name = std::env::var("MYCRAFT_BACKEND").ok()?;
+    match name.to_lowercase().as_str() {
+        "vulkan" => Some(wgpu::BackendBit::VULKAN),
+        "metal" => Some(wgpu::BackendBit::METAL),
+        "dx12" => Some(wgpu::BackendBit::DX12),
+        "dx11" => Some(wgpu::BackendBit::DX11),
+        "gl" => Some(wgpu::BackendBit::GL),
+        _ => {
+            log::warn!("Unknown MYCRAFT_BACKEND {:?}, expected vulkan, metal, dx12, dx11 or gl. Using the default backends", name);
+            None
+        }
+    }
+}
+
+/// Movement is integrated in steps of this duration whatever the frame rate, so that it behaves the same at 30 and 300 FPS
+const PHYSICS_TIMESTEP: std::time::Duration = std::time::Duration::from_nanos(1_000_000_000 / 60);
+/// After a long hitch we drop the time beyond this many steps instead of freezing while catching up.
+/// How far the camera can move in those steps is bounded separately, by `CameraController::max_step`.
+const MAX_PHYSICS_STEPS_PER_FRAME: u32 = 8;
+
+/// Splits `lag`, the time not simulated yet, into the number of physics steps to run this frame and the time left for the next ones.
+/// The lag beyond `MAX_PHYSICS_STEPS_PER_FRAME` steps is dropped.
+fn physics_steps(lag: std::time::Duration) -> (u32, std::time::Duration) {
+    let full_steps = lag.as_nanos() / PHYSICS_TIMESTEP.as_nanos();
+    if full_steps > MAX_PHYSICS_STEPS_PER_FRAME as u128 {
+        (MAX_PHYSICS_STEPS_PER_FRAME, std::time::Duration::ZERO)
+    } else {
+        let full_steps = full_steps as u32;
+        (full_steps, lag - PHYSICS_TIMESTEP * full_steps)
+    }
+}
+
+/// Tries the preferred GPU first, then falls back to anything that works.
+/// When a window is given, the adapter must be able to present to it and its surface is returned alongside.
+async fn request_adapter(window: Option<&Window>) -> Result<(wgpu::Adapter, Option<wgpu::Surface>), String> {
+    let attempts = match backend_from_env() {
+        // Don't fall back to other backends, that would defeat the purpose of forcing one
+        Some(backend) => vec![
+            (backend, wgpu::PowerPreference::HighPerformance),
+            (backend, wgpu::PowerPreference::LowPower),
+        ],
+        // BackendBit::PRIMARY => Vulkan + Metal + DX12 + Browser WebGPU
+        None => vec![
+            (wgpu::BackendBit::PRIMARY, wgpu::PowerPreference::HighPerformance),
+            (wgpu::BackendBit::PRIMARY, wgpu::PowerPreference::LowPower),
+            (wgpu::BackendBit::all(), wgpu::PowerPreference::default()),
+        ],
+    };
+    let mut tried = Vec::new();
+    for &(backends, power_preference) in attempts.iter() {
+        // The instance is a handle to our GPU
+        let instance = wgpu::Instance::new(backends);
+        let surface = window.map(|window| unsafe { instance.create_surface(window) });
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference,
+                compatible_surface: surface.as_ref(),
+            })
+            .await;
+        match adapter {
+            Some(adapter) => {
+                log::info!("Using GPU adapter {:?}", adapter.get_info());
+                return Ok((adapter, surface));
+            }
+            None => tried.push(format!("{:?} ({:?})", backends, power_preference)),
+        }
+    }
+    Err(format!(
+        "No suitable GPU adapter found. Tried the following backends: {}",
+        tried.join(", ")
+    ))
+}
+
+async fn request_device(adapter: &wgpu::Adapter) -> Result<(wgpu::Device, wgpu::Queue), String> {
+    adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                label: None,
+                features: wgpu::Features::empty(),
+                limits: wgpu::Limits::default(),
+            },
+            None, // Trace path
+        )
+        .await
+        .map_err(|err| format!("Failed to create the GPU device: {}", err))
+}
+
+/// Name of the last GPU operation we started. wgpu reports errors asynchronously and without
+/// telling us what caused them, so we log this alongside them to know where to start looking.
+#[derive(Clone)]
+struct GpuOperation(Arc<Mutex<&'static str>>);
+
+impl GpuOperation {
+    fn new() -> Self {
+        Self(Arc::new(Mutex::new("creating the device")))
+    }
+
+    fn set(&self, operation: &'static str) {
+        *self.0.lock().unwrap() = operation;
+    }
+
+    fn get(&self) -> &'static str {
+        *self.0.lock().unwrap()
+    }
+
+    /// Replaces wgpu's default handler, which panics with no context, by one that logs the error and the last operation
+    fn log_uncaptured_errors(&self, device: &wgpu::Device) {
+        let operation = self.clone();
+        device.on_uncaptured_error(move |err| match err {
+            wgpu::Error::ValidationError { description, .. } => {
+                log::error!("GPU validation error while {}: {}", operation.get(), description)
+            }
+            wgpu::Error::OutOfMemoryError { source } => {
+                log::error!("GPU out of memory while {}: {}", operation.get(), source)
+            }
+        });
+    }
+}
+
+/// A texture with the same size and format as the render target, that can be copied from
+fn create_offscreen_texture(
+    device: &wgpu::Device,
+    sc_desc: &wgpu::SwapChainDescriptor,
+) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Offscreen Texture"),
+        size: wgpu::Extent3d {
+            width: sc_desc.width,
+            height: sc_desc.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: sc_desc.format,
+        usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::COPY_SRC,
+    })
+}
+
+/// Loads every texture set of `asset_source`. Files that fail to load are replaced by a magenta placeholder, so that they stand out.
+/// They can all be swapped into the same bind group: `Texture::from_image` always creates 2D Rgba8UnormSrgb textures.
+fn load_diffuse_textures(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    asset_source: &AssetSource,
+    filter: wgpu::FilterMode,
+) -> Vec<(String, texture::Texture)> {
+    let mut textures: Vec<_> = asset_source
+        .texture_names()
+        .into_iter()
+        .map(|name| {
+            let texture = match asset_source.path(&name) {
+                Some(path) => texture::Texture::from_path(device, queue, &path, &name, filter).unwrap_or_else(|err| {
+                    log::warn!("{:#}. Using a placeholder texture", err);
+                    texture::Texture::missing(device, queue, &name)
+                }),
+                None => {
+                    let bytes = asset_source.load_bytes(&name);
+                    texture::Texture::from_bytes(device, queue, &bytes, &name, filter)
+                        .expect("Embedded textures are valid images")
+                }
+            };
+            (name, texture)
+        })
+        .collect();
+    if textures.is_empty() {
+        log::warn!("No texture found. Using a placeholder texture");
+        let name = "missing texture".to_string();
+        textures.push((name.clone(), texture::Texture::missing(device, queue, &name)));
+    }
+    textures
+}
+
+/// Width / height of a physical size, or `None` if it is empty (e.g. a minimized window).
+/// Always computed from physical pixels: after a DPI change the logical size may stay the same while the physical one doesn't.
+fn aspect_ratio(size: winit::dpi::PhysicalSize<u32>) -> Option<f32> {
+    if size.width == 0 || size.height == 0 {
+        None
+    } else {
+        Some(size.width as f32 / size.height as f32)
+    }
+}
+
+/// Where the frames end up: presented to a window, or kept in a texture when running headless
+enum RenderTarget {
+    Window {
+        surface: wgpu::Surface,
+        swap_chain: wgpu::SwapChain,
+    },
+    Offscreen {
+        texture: wgpu::Texture,
+        view: wgpu::TextureView,
+    },
+}
 
 struct State {
-    surface: wgpu::Surface,
     device: wgpu::Device,
     queue: wgpu::Queue,
     sc_desc: wgpu::SwapChainDescriptor,
-    swap_chain: wgpu::SwapChain,
+    render_target: RenderTarget,
     size: winit::dpi::PhysicalSize<u32>,
+    asset_source: AssetSource,
+    shader: wgpu::ShaderModule,
+    sky_shader: wgpu::ShaderModule,
+    render_pipeline_layout: wgpu::PipelineLayout,
+    sky_render_pipeline_layout: wgpu::PipelineLayout,
     render_pipeline: wgpu::RenderPipeline,
     sky_render_pipeline: wgpu::RenderPipeline,
+    sample_count: u32,
+    cull_mode: Option<wgpu::Face>,
+    multisampled_framebuffer: Option<wgpu::TextureView>,
+    // Fraction of the window resolution the scene is rendered at, from 0.25 to 1
+    render_scale: f32,
+    // Only there when render_scale is below 1
+    upscaler: Option<Upscaler>,
     vertex_buffer: wgpu::Buffer,
+    ambient_light: AmbientLight,
     index_buffer: wgpu::Buffer,
     num_indices: u32,
     sky_vertex_buffer: wgpu::Buffer,
     sky_index_buffer: wgpu::Buffer,
     sky_num_indices: u32,
-    diffuse_texture: texture::Texture,
+    // Every texture set found in the assets, by file name. Never empty.
+    diffuse_textures: Vec<(String, texture::Texture)>,
+    // Index in diffuse_textures of the one bound as diffuse_bind_group
+    active_texture: usize,
+    texture_bind_group_layout: wgpu::BindGroupLayout,
+    texture_filter: wgpu::FilterMode,
+    // Maximum anisotropic filtering level: 1 (off), 2, 4, 8 or 16
+    texture_anisotropy: u8,
     diffuse_bind_group: wgpu::BindGroup,
     camera: Camera,
+    // Where the camera was before the last physics step, to interpolate between the last two steps when rendering
+    previous_camera_position: cgmath::Point3<f32>,
+    // Time not yet simulated, always less than PHYSICS_TIMESTEP after an update
+    physics_lag: std::time::Duration,
     camera_controller: CameraController,
     uniforms: Uniforms,
     uniform_buffer: wgpu::Buffer,
+    light: Light,
+    light_buffer: wgpu::Buffer,
+    sky: Sky,
+    sky_buffer: wgpu::Buffer,
     uniform_bind_group: wgpu::BindGroup,
     input_state: InputState,
+    frame_timer: FrameTimer,
+    gamepad: GamepadInput,
+    line_renderer: LineRenderer,
+    minimap: Minimap,
+    is_debug_drawing_enabled: bool,
+    // Only visible where the sky doesn't cover the screen
+    clear_color: wgpu::Color,
+    // Maximum frames per second, on top of vsync. None means uncapped.
+    frame_cap: Option<f32>,
+    // From 0 to 1, 0 is midnight and 0.5 is noon. Drives the sun, the sky and the fog.
+    time_of_day: f32,
+    // While off, time stands still and the light, sky, fog and clear color keep whatever their setters gave them
+    is_day_cycle_enabled: bool,
+    gpu_operation: GpuOperation,
 }
 
 impl State {
-    async fn new(window: &Window) -> Self {
+    async fn new(window: &Window, spawn: Spawn, asset_source: AssetSource, settings: &Settings) -> Result<Self, String> {
         let size = window.inner_size();
 
-        // The instance is a handle to our GPU
-        // BackendBit::PRIMARY => Vulkan + Metal + DX12 + Browser WebGPU
-        let instance = wgpu::Instance::new(wgpu::BackendBit::PRIMARY);
-        let surface = unsafe { instance.create_surface(window) };
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
-                compatible_surface: Some(&surface),
-            })
-            .await
-            .unwrap();
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    label: None,
-                    features: wgpu::Features::empty(),
-                    limits: wgpu::Limits::default(),
-                },
-                None, // Trace path
-            )
-            .await
-            .unwrap();
+        let (adapter, surface) = request_adapter(Some(window)).await?;
+        let surface = surface.unwrap();
+        let (device, queue) = request_device(&adapter).await?;
 
         let sc_desc = wgpu::SwapChainDescriptor {
             usage: wgpu::TextureUsage::RENDER_ATTACHMENT,
-            format: adapter.get_swap_chain_preferred_format(&surface).unwrap(),
+            format: adapter
+                .get_swap_chain_preferred_format(&surface)
+                .ok_or_else(|| "The GPU adapter can't present to this window".to_string())?,
             width: size.width,
             height: size.height,
             present_mode: wgpu::PresentMode::Fifo,
         };
         let swap_chain = device.create_swap_chain(&surface, &sc_desc);
 
-        let diffuse_bytes = include_bytes!("happy-tree.png");
-        let diffuse_texture =
-            texture::Texture::from_bytes(&device, &queue, diffuse_bytes, "happy-tree.png").unwrap();
-
-        let texture_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStage::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            multisampled: false,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStage::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler {
-                            comparison: false,
-                            filtering: true,
-                        },
-                        count: None,
-                    },
-                ],
-                label: Some("texture_bind_group_layout"),
-            });
+        Ok(Self::from_device(device, queue, sc_desc, RenderTarget::Window { surface, swap_chain }, spawn, asset_source, settings))
+    }
 
-        let diffuse_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &texture_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-                },
-            ],
-            label: Some("diffuse_bind_group"),
-        });
+    /// Creates a State that renders into an offscreen texture instead of a window.
+    /// Use `capture_frame` to get the rendered image back. The golden image test renders with it.
+    #[cfg_attr(not(test), allow(dead_code))]
+    async fn new_headless(
+        width: u32,
+        height: u32,
+        spawn: Spawn,
+        asset_source: AssetSource,
+        settings: &Settings,
+    ) -> Result<Self, String> {
+        let (adapter, _) = request_adapter(None).await?;
+        let (device, queue) = request_device(&adapter).await?;
+
+        // Not used to create a swap chain, but it conveniently describes our render target
+        let sc_desc = wgpu::SwapChainDescriptor {
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::COPY_SRC,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::Fifo,
+        };
+        let texture = create_offscreen_texture(&device, &sc_desc);
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Ok(Self::from_device(device, queue, sc_desc, RenderTarget::Offscreen { texture, view }, spawn, asset_source, settings))
+    }
+
+    fn from_device(
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        sc_desc: wgpu::SwapChainDescriptor,
+        render_target: RenderTarget,
+        spawn: Spawn,
+        asset_source: AssetSource,
+        settings: &Settings,
+    ) -> Self {
+        let size = winit::dpi::PhysicalSize::new(sc_desc.width, sc_desc.height);
+
+        let gpu_operation = GpuOperation::new();
+        gpu_operation.log_uncaptured_errors(&device);
+        gpu_operation.set("loading the resources");
 
-        let camera = Camera::new(sc_desc.width as f32 / sc_desc.height as f32);
-        let camera_controller = CameraController::new(0.2);
+        let texture_filter = wgpu::FilterMode::Linear;
+        let diffuse_textures = load_diffuse_textures(&device, &queue, &asset_source, texture_filter);
+        // Prefer the texture we have always shipped with, if it is among them
+        let active_texture = diffuse_textures
+            .iter()
+            .position(|(name, _)| name == "happy-tree.png")
+            .unwrap_or(0);
+        log::info!("Texture set: {} ({}/{})", diffuse_textures[active_texture].0, active_texture + 1, diffuse_textures.len());
+
+        let texture_bind_group_layout = render::create_texture_bind_group_layout(&device);
+
+        let diffuse_bind_group =
+            render::create_texture_bind_group(&device, &texture_bind_group_layout, &diffuse_textures[active_texture].1);
+
+        let camera = Camera::with_transform(
+            sc_desc.width as f32 / sc_desc.height as f32,
+            spawn.position,
+            spawn.yaw,
+            spawn.pitch,
+        );
+        let camera_controller = CameraController::new(settings.camera_speed);
 
         let mut uniforms = Uniforms::new();
         uniforms.update_view_proj(&camera);
+        // Fade out right before the far plane culls the geometry
+        uniforms.set_fog([0.75, 0.85, 0.95], camera.zfar() * 0.6, camera.zfar());
 
         let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Uniform Buffer"),
@@ -324,46 +573,52 @@ impl State {
             usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
         });
 
-        let uniform_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStage::VERTEX,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }],
-                label: Some("uniform_bind_group_layout"),
-            });
+        let light = Light::new((-0.5, -0.3, -1.0).into(), [1.0, 1.0, 1.0]);
 
-        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &uniform_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: uniform_buffer.as_entire_binding(),
-            }],
-            label: Some("uniform_bind_group"),
+        let light_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Light Buffer"),
+            contents: bytemuck::cast_slice(&[light]),
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
         });
 
-        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
-            label: Some("Shader"),
-            flags: wgpu::ShaderFlags::all(),
-            source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
+        let sky = Sky::new([0.25, 0.45, 0.85], [0.75, 0.85, 0.95]);
+
+        let sky_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Sky Buffer"),
+            contents: bytemuck::cast_slice(&[sky]),
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
         });
 
-        let sky_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
-            label: Some("Sky Shader"),
-            flags: wgpu::ShaderFlags::all(),
-            source: wgpu::ShaderSource::Wgsl(include_str!("sky.wgsl").into()),
+        let uniform_bind_group_layout = render::create_uniform_bind_group_layout(&device);
+
+        let uniform_bind_group = render::create_uniform_bind_group(
+            &device,
+            &uniform_bind_group_layout,
+            &uniform_buffer,
+            &light_buffer,
+            &sky_buffer,
+        );
+
+        let (vertices, indices) = mesh::cube_mesh();
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Vertex Buffer"),
+            contents: bytemuck::cast_slice(&vertices),
+            // Also read by the ambient light compute shader
+            usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::STORAGE,
         });
+        let ambient_light = AmbientLight::new(&device, &vertex_buffer, vertices.len() as u32, &sky_buffer, &asset_source);
+
+        let shader = render::create_wgsl_shader(&device, "Shader", &asset_source.load_text("shader.wgsl"));
+        let sky_shader = render::create_wgsl_shader(&device, "Sky Shader", &asset_source.load_text("sky.wgsl"));
 
         let render_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Render Pipeline Layout"),
-                bind_group_layouts: &[&texture_bind_group_layout, &uniform_bind_group_layout],
+                bind_group_layouts: &[
+                    &texture_bind_group_layout,
+                    &uniform_bind_group_layout,
+                    ambient_light.bind_group_layout(),
+                ],
                 push_constant_ranges: &[],
             });
 
@@ -374,94 +629,44 @@ impl State {
                 push_constant_ranges: &[],
             });
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "main",
-                buffers: &[Vertex::desc()],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "main",
-                targets: &[wgpu::ColorTargetState {
-                    format: sc_desc.format,
-                    blend: Some(wgpu::BlendState {
-                        color: wgpu::BlendComponent::REPLACE,
-                        alpha: wgpu::BlendComponent::REPLACE,
-                    }),
-                    write_mask: wgpu::ColorWrite::ALL,
-                }],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
-                polygon_mode: wgpu::PolygonMode::Fill,
-                // Requires Features::DEPTH_CLAMPING
-                clamp_depth: false,
-                // Requires Features::CONSERVATIVE_RASTERIZATION
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-        });
-
-        let sky_render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Sky Pipeline"),
-            layout: Some(&sky_render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &sky_shader,
-                entry_point: "main",
-                buffers: &[Vertex::desc()],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &sky_shader,
-                entry_point: "main",
-                targets: &[wgpu::ColorTargetState {
-                    format: sc_desc.format,
-                    blend: Some(wgpu::BlendState {
-                        color: wgpu::BlendComponent::REPLACE,
-                        alpha: wgpu::BlendComponent::REPLACE,
-                    }),
-                    write_mask: wgpu::ColorWrite::ALL,
-                }],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                polygon_mode: wgpu::PolygonMode::Fill,
-                clamp_depth: false,
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-        });
+        let sample_count = 1;
+        let cull_mode = Some(wgpu::Face::Back);
+        let render_pipeline = render::create_render_pipeline(
+            &device,
+            &render_pipeline_layout,
+            &shader,
+            sc_desc.format,
+            sample_count,
+            cull_mode,
+            "Render Pipeline",
+        );
+        let sky_render_pipeline = render::create_render_pipeline(
+            &device,
+            &sky_render_pipeline_layout,
+            &sky_shader,
+            sc_desc.format,
+            sample_count,
+            Some(wgpu::Face::Back),
+            "Sky Pipeline",
+        );
+        let multisampled_framebuffer = None;
+        let line_renderer = LineRenderer::new(&device, &uniform_bind_group_layout, sc_desc.format, sample_count, &asset_source);
+        let minimap = Minimap::new(
+            &device,
+            &uniform_bind_group_layout,
+            &light_buffer,
+            &sky_buffer,
+            sc_desc.format,
+            sample_count,
+            &asset_source,
+        );
 
-        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Vertex Buffer"),
-            contents: bytemuck::cast_slice(VERTICES),
-            usage: wgpu::BufferUsage::VERTEX,
-        });
         let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Index Buffer"),
-            contents: bytemuck::cast_slice(INDICES),
+            contents: bytemuck::cast_slice(&indices),
             usage: wgpu::BufferUsage::INDEX,
         });
-        let num_indices = INDICES.len() as u32;
+        let num_indices = indices.len() as u32;
 
         let sky_vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Sky Vertex Buffer"),
@@ -477,41 +682,403 @@ impl State {
 
         let input_state = InputState {
             is_cursor_captured: false,
+            held_keys: HashSet::new(),
         };
 
-        Self {
-            surface,
+        let mut state = Self {
             device,
             queue,
             sc_desc,
-            swap_chain,
+            render_target,
             size,
+            shader,
+            sky_shader,
+            render_pipeline_layout,
+            sky_render_pipeline_layout,
             render_pipeline,
             sky_render_pipeline,
+            sample_count,
+            cull_mode,
+            multisampled_framebuffer,
+            render_scale: 1.,
+            upscaler: None,
             vertex_buffer,
+            ambient_light,
             index_buffer,
             num_indices,
             sky_vertex_buffer,
             sky_index_buffer,
             sky_num_indices,
-            diffuse_texture,
+            diffuse_textures,
+            active_texture,
+            texture_bind_group_layout,
+            texture_filter,
+            texture_anisotropy: 1,
             diffuse_bind_group,
+            previous_camera_position: camera.position(),
+            physics_lag: std::time::Duration::ZERO,
             camera,
             camera_controller,
             uniform_buffer,
             uniform_bind_group,
             uniforms,
+            light,
+            light_buffer,
+            sky,
+            sky_buffer,
             input_state,
-        }
+            gpu_operation,
+            asset_source,
+            frame_timer: FrameTimer::new(),
+            gamepad: GamepadInput::new(),
+            line_renderer,
+            minimap,
+            is_debug_drawing_enabled: false,
+            clear_color: wgpu::Color::TRANSPARENT,
+            frame_cap: None,
+            time_of_day: 0.,
+            is_day_cycle_enabled: true,
+        };
+        state.apply_settings(settings);
+        // Mid-morning
+        state.set_time_of_day(0.35);
+        state
     }
 
+    /// `new_size` must be the physical size: it is what the swap chain is made of, and the aspect ratio is computed from it.
     fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
+        // Minimized windows report a 0 size, which would make an invalid swap chain and a NaN aspect ratio.
+        // We keep the previous size until the window comes back.
+        let aspect = match aspect_ratio(new_size) {
+            Some(aspect) => aspect,
+            None => return,
+        };
+        self.gpu_operation.set("resizing the render target");
         self.size = new_size;
         self.sc_desc.width = new_size.width;
         self.sc_desc.height = new_size.height;
-        self.swap_chain = self.device.create_swap_chain(&self.surface, &self.sc_desc);
+        match &mut self.render_target {
+            RenderTarget::Window { surface, swap_chain } => {
+                *swap_chain = self.device.create_swap_chain(surface, &self.sc_desc);
+            }
+            RenderTarget::Offscreen { texture, view } => {
+                *texture = create_offscreen_texture(&self.device, &self.sc_desc);
+                *view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+            }
+        }
+        self.recreate_framebuffers();
+
+        self.camera.aspect = aspect;
+    }
+
+    fn set_sample_count(&mut self, sample_count: u32) {
+        self.gpu_operation.set("changing the MSAA sample count");
+        self.sample_count = if render::is_sample_count_supported(sample_count) {
+            sample_count
+        } else {
+            log::warn!("MSAA x{} is not supported, falling back to no MSAA", sample_count);
+            1
+        };
+        self.render_pipeline = self.create_cube_pipeline();
+        self.sky_render_pipeline = render::create_render_pipeline(
+            &self.device,
+            &self.sky_render_pipeline_layout,
+            &self.sky_shader,
+            self.sc_desc.format,
+            self.sample_count,
+            Some(wgpu::Face::Back),
+            "Sky Pipeline",
+        );
+        self.line_renderer.set_sample_count(&self.device, self.sc_desc.format, self.sample_count);
+        self.minimap.set_sample_count(&self.device, self.sample_count);
+        self.recreate_framebuffers();
+    }
+
+    /// Resolution the scene is rendered at, before being upscaled to the window
+    fn render_size(&self) -> (u32, u32) {
+        let scale = |length: u32| ((length as f32 * self.render_scale).round() as u32).max(1);
+        (scale(self.sc_desc.width), scale(self.sc_desc.height))
+    }
+
+    /// Rebuilds the intermediate textures the scene is rendered into, after a change of size, sample count or render scale
+    fn recreate_framebuffers(&mut self) {
+        let (width, height) = self.render_size();
+        self.multisampled_framebuffer = if self.sample_count > 1 {
+            Some(render::create_multisampled_framebuffer(
+                &self.device,
+                self.sc_desc.format,
+                width,
+                height,
+                self.sample_count,
+            ))
+        } else {
+            None
+        };
+        if self.render_scale < 1. {
+            match &mut self.upscaler {
+                Some(upscaler) => upscaler.resize(&self.device, width, height),
+                None => {
+                    self.upscaler = Some(Upscaler::new(&self.device, self.sc_desc.format, width, height, &self.asset_source))
+                }
+            }
+        } else {
+            self.upscaler = None;
+        }
+    }
+
+    /// Renders the scene at a fraction of the window resolution, clamped between 0.25 and 1, to go easier on weak GPUs
+    fn set_render_scale(&mut self, render_scale: f32) {
+        self.gpu_operation.set("changing the render scale");
+        self.render_scale = render_scale.clamp(0.25, 1.);
+        self.recreate_framebuffers();
+        let (width, height) = self.render_size();
+        log::info!("Render scale {}%: {}x{}", (self.render_scale * 100.).round(), width, height);
+    }
+
+    fn create_cube_pipeline(&self) -> wgpu::RenderPipeline {
+        render::create_render_pipeline(
+            &self.device,
+            &self.render_pipeline_layout,
+            &self.shader,
+            self.sc_desc.format,
+            self.sample_count,
+            self.cull_mode,
+            "Render Pipeline",
+        )
+    }
+
+    /// Re-reads shader.wgsl from the asset directory and rebuilds the cube pipeline with it, to iterate on the shader without recompiling.
+    /// Does nothing with embedded assets, since they can't have changed.
+    fn reload_shader(&mut self) {
+        if !self.asset_source.is_reloadable() {
+            log::warn!("Shader reloading needs the assets to be read from a directory, not embedded");
+            return;
+        }
+        let source = self.asset_source.load_text("shader.wgsl");
+        if let Err(err) = render::validate_wgsl(&source) {
+            log::error!("Keeping the previous shader, shader.wgsl is invalid:\n{}", err);
+            return;
+        }
+        self.gpu_operation.set("reloading the shader");
+        self.shader = render::create_wgsl_shader(&self.device, "Shader", &source);
+        self.render_pipeline = self.create_cube_pipeline();
+        log::info!("Reloaded shader.wgsl");
+    }
+
+    /// Switches between back-face culling and drawing both sides, to spot triangles with the wrong winding
+    fn toggle_back_face_culling(&mut self) {
+        self.cull_mode = match self.cull_mode {
+            Some(_) => None,
+            None => Some(wgpu::Face::Back),
+        };
+        self.gpu_operation.set("toggling back-face culling");
+        self.render_pipeline = self.create_cube_pipeline();
+        log::info!("Back-face culling {}", if self.cull_mode.is_some() { "on" } else { "off" });
+    }
+
+    /// Switches between no MSAA and MSAA x4, the only count we can rely on (see `render::is_sample_count_supported`)
+    fn toggle_msaa(&mut self) {
+        self.set_sample_count(if self.sample_count > 1 { 1 } else { 4 });
+        log::info!("MSAA x{}", self.sample_count);
+    }
+
+    /// Switches the block textures between smooth (linear) and pixelated (nearest) magnification
+    fn toggle_texture_filter(&mut self) {
+        self.texture_filter = match self.texture_filter {
+            wgpu::FilterMode::Linear => wgpu::FilterMode::Nearest,
+            wgpu::FilterMode::Nearest => wgpu::FilterMode::Linear,
+        };
+        self.update_texture_sampler();
+        log::info!("Texture filter: {:?}", self.texture_filter);
+        if self.texture_anisotropy > 1 {
+            self.log_texture_anisotropy();
+        }
+    }
+
+    /// Goes through anisotropic filtering levels 1 (off), 2, 4, 8 and 16
+    fn cycle_texture_anisotropy(&mut self) {
+        self.texture_anisotropy = if self.texture_anisotropy >= 16 { 1 } else { self.texture_anisotropy * 2 };
+        self.update_texture_sampler();
+        self.log_texture_anisotropy();
+    }
+
+    fn log_texture_anisotropy(&self) {
+        if self.texture_anisotropy <= 1 {
+            log::info!("Anisotropic filtering off");
+        } else if self.texture_filter == wgpu::FilterMode::Nearest {
+            log::warn!(
+                "Anisotropic filtering x{} is not used with nearest filtering, switch to linear filtering (N) to use it",
+                self.texture_anisotropy
+            );
+        } else {
+            // wgpu 0.8 doesn't tell us whether the adapter supports it, and silently drops it if it doesn't
+            log::info!(
+                "Anisotropic filtering x{} requested, it may be ignored by this adapter",
+                self.texture_anisotropy
+            );
+        }
+    }
+
+    /// Switches the cube to the next texture set found in the assets, to compare them at runtime
+    fn cycle_texture(&mut self) {
+        self.active_texture = (self.active_texture + 1) % self.diffuse_textures.len();
+        // The sampler settings may have changed since this texture was last bound
+        self.update_texture_sampler();
+        log::info!(
+            "Texture set: {} ({}/{})",
+            self.diffuse_textures[self.active_texture].0,
+            self.active_texture + 1,
+            self.diffuse_textures.len()
+        );
+    }
+
+    fn update_texture_sampler(&mut self) {
+        self.gpu_operation.set("updating the texture sampler");
+        let texture = &mut self.diffuse_textures[self.active_texture].1;
+        texture.set_sampler(&self.device, self.texture_filter, self.texture_anisotropy);
+        self.diffuse_bind_group = render::create_texture_bind_group(&self.device, &self.texture_bind_group_layout, texture);
+    }
+
+    /// Overwritten by the day cycle on the next update, unless it is turned off
+    #[allow(dead_code)]
+    fn set_light_direction(&mut self, direction: cgmath::Vector3<f32>) {
+        self.light = Light::new(direction, self.light.color);
+        self.queue.write_buffer(&self.light_buffer, 0, bytemuck::cast_slice(&[self.light]));
+    }
+
+    /// The fog is written to the GPU with the rest of the uniforms on the next update.
+    /// While the day cycle runs, it keeps `start` and `end` but replaces `color` with the sky's.
+    #[allow(dead_code)]
+    fn set_fog(&mut self, color: [f32; 3], start: f32, end: f32) {
+        self.uniforms.set_fog(color, start, end);
+    }
+
+    /// Overwritten by the day cycle on the next update, unless it is turned off
+    #[allow(dead_code)]
+    fn set_sky_colors(&mut self, top: [f32; 3], bottom: [f32; 3]) {
+        self.sky = Sky::new(top, bottom);
+        self.queue.write_buffer(&self.sky_buffer, 0, bytemuck::cast_slice(&[self.sky]));
+    }
+
+    fn apply_settings(&mut self, settings: &Settings) {
+        self.camera_controller.mouse_sensitivity = settings.mouse_sensitivity;
+        self.camera_controller.set_invert_y(settings.invert_y);
+        self.camera_controller.look_smoothing = settings.look_smoothing;
+        self.camera_controller.set_speed(settings.camera_speed);
+        self.camera_controller.max_step = settings.max_step;
+        self.camera.set_fov_mode(settings.fov_mode);
+        self.camera.set_fov(settings.fov);
+        self.camera_controller.speed_fov_boost = settings.speed_fov_boost;
+        self.camera.set_clip_planes(settings.near_plane, settings.render_distance);
+        // Fade out right before the far plane culls the geometry
+        let zfar = self.camera.zfar();
+        self.uniforms.set_fog(self.uniforms.fog_color, zfar * 0.6, zfar);
+        self.minimap.set_size(&self.device, settings.minimap_size, self.sample_count);
+        self.minimap.corner = settings.minimap_corner;
+        self.minimap.is_enabled = settings.show_minimap;
+        self.set_frame_cap(if settings.frame_cap == 0. { None } else { Some(settings.frame_cap) });
+    }
+
+    /// `None` removes the cap
+    fn set_frame_cap(&mut self, frame_cap: Option<f32>) {
+        match frame_cap {
+            Some(fps) if !(fps.is_finite() && fps > 0.) => log::warn!("Ignoring invalid frame cap {}", fps),
+            _ => self.frame_cap = frame_cap,
+        }
+    }
+
+    /// Sleeps for what remains of the frame budget, if there is a frame cap
+    fn wait_for_frame_cap(&self) {
+        if let Some(fps) = self.frame_cap {
+            let budget = std::time::Duration::from_secs_f32(1. / fps);
+            let elapsed = self.frame_timer.time_since_last_frame();
+            if elapsed < budget {
+                std::thread::sleep(budget - elapsed);
+            }
+        }
+    }
 
-        self.camera.aspect = self.sc_desc.width as f32 / self.sc_desc.height as f32;
+    /// Overwritten by the day cycle on the next update, unless it is turned off
+    #[allow(dead_code)]
+    fn set_clear_color(&mut self, color: wgpu::Color) {
+        self.clear_color = color;
+    }
+
+    /// Queues a debug segment for the current frame. It is only drawn while debug drawing is enabled (F3).
+    fn debug_line(&mut self, a: cgmath::Point3<f32>, b: cgmath::Point3<f32>, color: [f32; 3]) {
+        if self.is_debug_drawing_enabled {
+            self.line_renderer.push(a, b, color);
+        }
+    }
+
+    /// Stops or resumes the passing of time, to keep the lighting set by hand
+    fn toggle_day_cycle(&mut self) {
+        self.is_day_cycle_enabled = !self.is_day_cycle_enabled;
+        log::info!("Day cycle {}", if self.is_day_cycle_enabled { "on" } else { "off" });
+    }
+
+    /// Applies the lighting of `time_of_day` right away, even if the day cycle is off.
+    /// `time_of_day` wraps around: 0 and 1 are both midnight, 0.25 is sunrise, 0.5 noon and 0.75 sunset
+    fn set_time_of_day(&mut self, time_of_day: f32) {
+        self.time_of_day = time_of_day.rem_euclid(1.);
+        let lighting = time_of_day::lighting_at(self.time_of_day);
+        self.light = Light::new(lighting.sun_direction, lighting.sun_color);
+        self.queue.write_buffer(&self.light_buffer, 0, bytemuck::cast_slice(&[self.light]));
+        self.set_sky_colors(lighting.sky_top_color, lighting.sky_bottom_color);
+        self.uniforms.set_fog(lighting.sky_bottom_color, self.uniforms.fog_start, self.uniforms.fog_end);
+        let [r, g, b] = lighting.sky_bottom_color;
+        self.clear_color = wgpu::Color { r: r as f64, g: g as f64, b: b as f64, a: 1. };
+    }
+
+    /// The change is written to the GPU with the rest of the uniforms on the next update
+    fn toggle_ambient_shading(&mut self) {
+        self.uniforms.ambient_shading = 1 - self.uniforms.ambient_shading;
+        log::info!("Ambient shading {}", if self.uniforms.ambient_shading == 1 { "on" } else { "off" });
+    }
+
+    /// Written to the GPU with the rest of the uniforms on the next update. Negative values are clamped to 0.
+    fn set_ambient_strength(&mut self, ambient_strength: f32) {
+        self.uniforms.ambient_strength = ambient_strength.max(0.);
+        log::info!("Ambient strength {:.2}", self.uniforms.ambient_strength);
+    }
+
+    /// Written to the GPU with the rest of the uniforms on the next update. Negative values are clamped to 0.
+    fn set_diffuse_strength(&mut self, diffuse_strength: f32) {
+        self.uniforms.diffuse_strength = diffuse_strength.max(0.);
+        log::info!("Diffuse strength {:.2}", self.uniforms.diffuse_strength);
+    }
+
+    /// Written to the GPU with the rest of the uniforms on the next update. Negative values are clamped to 0.
+    fn set_specular_strength(&mut self, specular_strength: f32) {
+        self.uniforms.specular_strength = specular_strength.max(0.);
+        log::info!("Specular strength {:.2}", self.uniforms.specular_strength);
+    }
+
+    /// Exponent of the specular highlights, at least 1. Written to the GPU with the rest of the uniforms on the next update.
+    fn set_shininess(&mut self, shininess: f32) {
+        self.uniforms.shininess = shininess.max(1.);
+        log::info!("Shininess {}", self.uniforms.shininess);
+    }
+
+    fn toggle_minimap(&mut self) {
+        self.minimap.is_enabled = !self.minimap.is_enabled;
+        log::info!("Minimap {}", if self.minimap.is_enabled { "on" } else { "off" });
+    }
+
+    /// Turns the camera towards the cube, or gives the control back if it is already turning
+    fn toggle_look_at_cube(&mut self) {
+        if self.camera_controller.has_look_target() {
+            self.camera_controller.cancel_look_target();
+        } else {
+            let cube_center = cgmath::Point3::new(0., 0., 0.);
+            self.camera_controller
+                .set_look_target(&self.camera, cube_center, std::time::Duration::from_millis(600));
+        }
+    }
+
+    fn toggle_debug_drawing(&mut self) {
+        self.is_debug_drawing_enabled = !self.is_debug_drawing_enabled;
+        log::info!("Debug drawing {}", if self.is_debug_drawing_enabled { "on" } else { "off" });
     }
 
     fn input(&mut self, event: &WindowEvent) -> bool {
@@ -522,18 +1089,92 @@ impl State {
         self.camera_controller.process_device_event(event, self.input_state.is_cursor_captured)
     }
 
-    fn update(&mut self) {
-        self.camera_controller.update_camera(&mut self.camera);
-        self.uniforms.update_view_proj(&self.camera);
+    fn fps(&self) -> f32 {
+        self.frame_timer.fps()
+    }
+
+    fn update(&mut self, app_state: AppState) {
+        // Always tick so that we don't get a huge dt when resuming
+        let dt = self.frame_timer.tick();
+        self.gpu_operation.set("updating the frame data");
+        self.gamepad.update(&mut self.camera_controller);
+        if app_state == AppState::Playing {
+            let (steps, lag) = physics_steps(self.physics_lag + dt);
+            self.physics_lag = lag;
+            let frame_start = self.camera.position();
+            for _ in 0..steps {
+                self.previous_camera_position = self.camera.position();
+                self.camera_controller.update_movement(&mut self.camera, PHYSICS_TIMESTEP);
+            }
+            if self.camera_controller.limit_frame_movement(&mut self.camera, frame_start) {
+                // The last step may be past the limited position, don't interpolate from there
+                self.previous_camera_position = self.camera.position();
+            }
+            // Looking around stays per-frame, so that the mouse feels as responsive as the frame rate allows
+            self.camera_controller.update_look(&mut self.camera, dt);
+            self.uniforms.time += dt.as_secs_f32();
+            if self.is_day_cycle_enabled {
+                // Hold T to fast-forward through the day
+                let time_scale = if self.input_state.held_keys.contains(&VirtualKeyCode::T) { 60. } else { 1. };
+                self.set_time_of_day(self.time_of_day + dt.as_secs_f32() * time_scale / time_of_day::DAY_DURATION);
+            }
+        }
+        // We are somewhere between the last two physics steps: show the camera there, so that motion stays smooth
+        // even when there isn't exactly one step per frame
+        let alpha = self.physics_lag.as_secs_f32() / PHYSICS_TIMESTEP.as_secs_f32();
+        let mut render_camera = self.camera.clone();
+        render_camera.set_position(
+            self.previous_camera_position + (self.camera.position() - self.previous_camera_position) * alpha,
+        );
+        self.uniforms.update_view_proj(&render_camera);
         self.queue.write_buffer(
             &self.uniform_buffer,
             0,
             bytemuck::cast_slice(&[self.uniforms]),
         );
+        if self.minimap.is_enabled {
+            self.minimap.update(&self.queue, &self.uniforms, &render_camera);
+        }
+        if self.is_debug_drawing_enabled {
+            // Bounds of the cube, and the world axes going through the origin
+            self.line_renderer.push_aabb((-1., -1., -1.).into(), (1., 1., 1.).into(), [1., 1., 0.]);
+            self.line_renderer.push_axes((0., 0., 0.).into(), 3.);
+            // Small axes right in front of the camera, so that they always sit in the middle of the screen
+            // and show which way X, Y and Z (up) point from the current point of view
+            let gizmo_position = render_camera.position() + render_camera.look_direction();
+            self.line_renderer.push_axes(gizmo_position, 0.05);
+        }
+        if self.camera_controller.has_look_target() {
+            // Where the camera is turning to (H), from just in front of it
+            let from = render_camera.position() + render_camera.look_direction();
+            self.debug_line(from, (0., 0., 0.).into(), [1., 0., 1.]);
+        }
+        self.line_renderer.upload(&self.device, &self.queue);
     }
 
     fn render(&mut self) -> Result<(), wgpu::SwapChainError> {
-        let frame = self.swap_chain.get_current_frame()?.output;
+        match &self.render_target {
+            RenderTarget::Window { swap_chain, .. } => {
+                let frame = swap_chain.get_current_frame()?.output;
+                self.render_to(&frame.view);
+            }
+            RenderTarget::Offscreen { view, .. } => self.render_to(view),
+        }
+        Ok(())
+    }
+
+    fn render_to(&self, target: &wgpu::TextureView) {
+        self.gpu_operation.set("rendering a frame");
+        // With a render scale below 1 the scene goes into a smaller texture that is stretched over the target at the end
+        let scene_target = match &self.upscaler {
+            Some(upscaler) => upscaler.view(),
+            None => target,
+        };
+        // With MSAA we draw into the multisampled framebuffer and resolve it into the target
+        let (view, resolve_target) = match &self.multisampled_framebuffer {
+            Some(multisampled_framebuffer) => (multisampled_framebuffer, Some(scene_target)),
+            None => (scene_target, None),
+        };
 
         let mut encoder = self
             .device
@@ -541,20 +1182,39 @@ impl State {
                 label: Some("Render Encoder"),
             });
 
+        self.ambient_light.compute(&mut encoder);
+
+        self.encode_scene(&mut encoder, view, resolve_target, &self.uniform_bind_group);
+
+        if let Some(upscaler) = &self.upscaler {
+            upscaler.draw(&mut encoder, target);
+        }
+        if self.minimap.is_enabled {
+            let (view, resolve_target) = self.minimap.render_targets();
+            self.encode_scene(&mut encoder, view, resolve_target, self.minimap.uniform_bind_group());
+            self.minimap.draw(&mut encoder, target, self.sc_desc.width, self.sc_desc.height);
+        }
+
+        self.queue.submit(iter::once(encoder.finish()));
+    }
+
+    /// Records the sky, the cube and the debug lines, seen through the point of view of `uniform_bind_group`
+    fn encode_scene(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        resolve_target: Option<&wgpu::TextureView>,
+        uniform_bind_group: &wgpu::BindGroup,
+    ) {
         // Fullscreen quad for the Sky
         {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Sky"),
                 color_attachments: &[wgpu::RenderPassColorAttachment {
-                    view: &frame.view,
-                    resolve_target: None,
+                    view,
+                    resolve_target,
                     ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.,
-                            g: 0.,
-                            b: 0.,
-                            a: 0.,
-                        }),
+                        load: wgpu::LoadOp::Clear(self.clear_color),
                         store: true,
                     },
                 }],
@@ -562,7 +1222,7 @@ impl State {
             });
 
             render_pass.set_pipeline(&self.sky_render_pipeline);
-            render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+            render_pass.set_bind_group(0, uniform_bind_group, &[]);
             render_pass.set_vertex_buffer(0, self.sky_vertex_buffer.slice(..));
             render_pass.set_index_buffer(self.sky_index_buffer.slice(..), wgpu::IndexFormat::Uint16);
             render_pass.draw_indexed(0..self.sky_num_indices, 0, 0..1);
@@ -572,8 +1232,8 @@ impl State {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
                 color_attachments: &[wgpu::RenderPassColorAttachment {
-                    view: &frame.view,
-                    resolve_target: None,
+                    view,
+                    resolve_target,
                     ops: wgpu::Operations {
                         load: wgpu::LoadOp::Load,
                         store: true,
@@ -584,15 +1244,119 @@ impl State {
 
             render_pass.set_pipeline(&self.render_pipeline);
             render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
-            render_pass.set_bind_group(1, &self.uniform_bind_group, &[]);
+            render_pass.set_bind_group(1, uniform_bind_group, &[]);
+            render_pass.set_bind_group(2, self.ambient_light.bind_group(), &[]);
             render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
             render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
             render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+
+            self.line_renderer.draw(&mut render_pass, uniform_bind_group);
         }
+    }
+
+    /// Renders a frame and reads it back from the GPU.
+    /// Swap chain frames can't be copied from, so in windowed mode this renders into a temporary texture.
+    /// Fails if the GPU can't map the frame back (e.g. the device was lost).
+    fn capture_frame(&self) -> Result<image::RgbaImage, String> {
+        self.gpu_operation.set("capturing a frame");
+        let temporary_texture;
+        let texture = match &self.render_target {
+            RenderTarget::Offscreen { texture, view } => {
+                self.render_to(view);
+                texture
+            }
+            RenderTarget::Window { .. } => {
+                temporary_texture = create_offscreen_texture(&self.device, &self.sc_desc);
+                self.render_to(&temporary_texture.create_view(&wgpu::TextureViewDescriptor::default()));
+                &temporary_texture
+            }
+        };
 
+        // Rows copied into a buffer must be padded to a multiple of 256 bytes
+        let unpadded_bytes_per_row = 4 * self.sc_desc.width;
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_bytes_per_row = (unpadded_bytes_per_row + align - 1) / align * align;
+
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Capture Buffer"),
+            size: (padded_bytes_per_row * self.sc_desc.height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::MAP_READ | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Capture Encoder"),
+            });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(padded_bytes_per_row),
+                    rows_per_image: std::num::NonZeroU32::new(self.sc_desc.height),
+                },
+            },
+            wgpu::Extent3d {
+                width: self.sc_desc.width,
+                height: self.sc_desc.height,
+                depth_or_array_layers: 1,
+            },
+        );
         self.queue.submit(iter::once(encoder.finish()));
 
-        Ok(())
+        let buffer_slice = buffer.slice(..);
+        let mapping = buffer_slice.map_async(wgpu::MapMode::Read);
+        self.device.poll(wgpu::Maintain::Wait);
+        futures::executor::block_on(mapping)
+            .map_err(|err| format!("Failed to read the frame back from the GPU: {}", err))?;
+
+        let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * self.sc_desc.height) as usize);
+        for row in buffer_slice
+            .get_mapped_range()
+            .chunks(padded_bytes_per_row as usize)
+        {
+            pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+        }
+        buffer.unmap();
+
+        if let wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb = self.sc_desc.format {
+            for pixel in pixels.chunks_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        image::RgbaImage::from_raw(self.sc_desc.width, self.sc_desc.height, pixels)
+            .ok_or_else(|| "The frame read back from the GPU is smaller than the render target".to_string())
+    }
+
+    /// Captures a frame and writes it to screenshots/, named after the current time. Returns the path of the new file.
+    fn save_screenshot(&self) -> anyhow::Result<std::path::PathBuf> {
+        use anyhow::Context;
+        let directory = std::path::Path::new("screenshots");
+        std::fs::create_dir_all(directory)
+            .with_context(|| format!("Failed to create {}", directory.display()))?;
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let path = directory.join(format!("screenshot-{}.png", timestamp));
+        self.capture_frame()
+            .map_err(anyhow::Error::msg)?
+            .save(&path)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(path)
+    }
+
+    /// Frees the cursor (e.g. to click in another window) without pausing, or captures it back
+    fn toggle_capture_cursor(&mut self, window: &Window) {
+        self.set_capture_cursor(window, !self.input_state.is_cursor_captured);
     }
 
     fn set_capture_cursor(&mut self, window: &Window, is_capturing: bool) {
@@ -617,18 +1381,77 @@ impl State {
 
 struct InputState {
     is_cursor_captured: bool,
+    held_keys: HashSet<VirtualKeyCode>,
+}
+
+impl InputState {
+    /// Keeps track of the held keys. Returns true only for the press that starts holding a key, not for the repeats that follow.
+    fn is_first_press(&mut self, input: &KeyboardInput) -> bool {
+        let key = match input.virtual_keycode {
+            Some(key) => key,
+            None => return false,
+        };
+        match input.state {
+            ElementState::Pressed => self.held_keys.insert(key),
+            ElementState::Released => {
+                self.held_keys.remove(&key);
+                false
+            }
+        }
+    }
+}
+
+/// While paused the cursor is released and the world stops updating, but we keep rendering
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum AppState {
+    Playing,
+    Paused,
+}
+
+/// Returns None if the image can't be decoded, in which case the window just keeps the default icon
+fn load_window_icon(asset_source: &AssetSource) -> Option<Icon> {
+    let img = match image::load_from_memory(&asset_source.load_bytes("happy-tree.png")) {
+        Ok(img) => img.to_rgba8(),
+        Err(err) => {
+            log::warn!("Could not decode the window icon: {}", err);
+            return None;
+        }
+    };
+    let (width, height) = img.dimensions();
+    match Icon::from_rgba(img.into_raw(), width, height) {
+        Ok(icon) => Some(icon),
+        Err(err) => {
+            log::warn!("Could not create the window icon: {}", err);
+            None
+        }
+    }
 }
 
 fn main() {
     env_logger::init();
+    let asset_source = AssetSource::default();
     let event_loop = EventLoop::new();
-    let window = WindowBuilder::new().build(&event_loop).unwrap();
+    let window = WindowBuilder::new()
+        .with_title("mycraft")
+        .with_window_icon(load_window_icon(&asset_source))
+        .build(&event_loop)
+        .unwrap();
 
     use futures::executor::block_on;
 
+    let settings = Settings::load_or_create(std::path::Path::new("settings.toml"));
     // Since main can't be async, we're going to need to block
-    let mut state = block_on(State::new(&window));
-    
+    let mut state = match block_on(State::new(&window, Spawn::default(), asset_source, &settings)) {
+        Ok(state) => state,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let mut app_state = AppState::Playing;
+    // What the window title shows, 0 until the first second has gone by
+    let mut shown_fps = 0;
     state.set_capture_cursor(&window, true);
 
     event_loop.run(move |event, _, control_flow| {
@@ -640,17 +1463,105 @@ fn main() {
                 if !state.input(event) {
                     match event {
                         WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
-                        WindowEvent::KeyboardInput { input, .. } => match input {
-                            KeyboardInput {
-                                state: ElementState::Pressed,
-                                virtual_keycode: Some(VirtualKeyCode::Escape),
-                                ..
-                            } => {
-                                state.set_capture_cursor(&window, false);
-                            },
-                            _ => {}
-                        },
+                        WindowEvent::KeyboardInput { input, .. } => {
+                            // OS key repeat keeps sending Pressed while a key is held, which would make the toggles flicker
+                            if state.input_state.is_first_press(input) {
+                                match input.virtual_keycode {
+                                    Some(VirtualKeyCode::Escape) => {
+                                        app_state = AppState::Paused;
+                                        state.set_capture_cursor(&window, false);
+                                    },
+                                    Some(VirtualKeyCode::M) => {
+                                        state.toggle_msaa();
+                                    },
+                                    Some(VirtualKeyCode::P) => {
+                                        state.camera.toggle_projection();
+                                    },
+                                    Some(VirtualKeyCode::F2) => match state.save_screenshot() {
+                                        Ok(path) => log::info!("Saved screenshot to {}", path.display()),
+                                        Err(err) => log::error!("Could not save screenshot: {:#}", err),
+                                    },
+                                    Some(VirtualKeyCode::F4) => {
+                                        state.toggle_minimap();
+                                    },
+                                    Some(VirtualKeyCode::F3) => {
+                                        state.toggle_debug_drawing();
+                                    },
+                                    Some(VirtualKeyCode::N) => {
+                                        state.toggle_texture_filter();
+                                    },
+                                    Some(VirtualKeyCode::F) => {
+                                        state.cycle_texture_anisotropy();
+                                    },
+                                    Some(VirtualKeyCode::C) => {
+                                        state.toggle_back_face_culling();
+                                    },
+                                    Some(VirtualKeyCode::G) => {
+                                        state.camera_controller.toggle_movement_basis();
+                                    },
+                                    Some(VirtualKeyCode::O) => {
+                                        state.toggle_ambient_shading();
+                                    },
+                                    Some(VirtualKeyCode::H) => {
+                                        state.toggle_look_at_cube();
+                                    },
+                                    Some(VirtualKeyCode::L) => {
+                                        state.toggle_day_cycle();
+                                    },
+                                    Some(VirtualKeyCode::F5) => {
+                                        state.cycle_texture();
+                                    },
+                                    Some(VirtualKeyCode::F10) => {
+                                        state.reload_shader();
+                                    },
+                                    // Lighting tuning, on the numpad
+                                    Some(VirtualKeyCode::Numpad1) => {
+                                        state.set_ambient_strength(state.uniforms.ambient_strength - 0.05);
+                                    },
+                                    Some(VirtualKeyCode::Numpad2) => {
+                                        state.set_ambient_strength(state.uniforms.ambient_strength + 0.05);
+                                    },
+                                    Some(VirtualKeyCode::Numpad3) => {
+                                        state.set_diffuse_strength(state.uniforms.diffuse_strength - 0.05);
+                                    },
+                                    Some(VirtualKeyCode::Numpad4) => {
+                                        state.set_diffuse_strength(state.uniforms.diffuse_strength + 0.05);
+                                    },
+                                    Some(VirtualKeyCode::Numpad5) => {
+                                        state.set_specular_strength(state.uniforms.specular_strength - 0.05);
+                                    },
+                                    Some(VirtualKeyCode::Numpad6) => {
+                                        state.set_specular_strength(state.uniforms.specular_strength + 0.05);
+                                    },
+                                    Some(VirtualKeyCode::Numpad7) => {
+                                        state.set_shininess(state.uniforms.shininess / 2.);
+                                    },
+                                    Some(VirtualKeyCode::Numpad8) => {
+                                        state.set_shininess(state.uniforms.shininess * 2.);
+                                    },
+                                    Some(VirtualKeyCode::LBracket) => {
+                                        state.set_render_scale(state.render_scale - 0.25);
+                                    },
+                                    Some(VirtualKeyCode::RBracket) => {
+                                        state.set_render_scale(state.render_scale + 0.25);
+                                    },
+                                    Some(VirtualKeyCode::Tab) => {
+                                        state.toggle_capture_cursor(&window);
+                                        // Same as clicking: capturing the cursor resumes the game
+                                        if state.input_state.is_cursor_captured {
+                                            app_state = AppState::Playing;
+                                        }
+                                    },
+                                    _ => {}
+                                }
+                            }
+                        }
+                        WindowEvent::Focused(false) => {
+                            // We won't be told about keys released while another window has the focus
+                            state.input_state.held_keys.clear();
+                        }
                         WindowEvent::MouseInput {button: MouseButton::Left, ..} => {
+                            app_state = AppState::Playing;
                             state.set_capture_cursor(&window, true);
                         }
                         WindowEvent::Resized(physical_size) => {
@@ -659,6 +1570,8 @@ fn main() {
                         WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
                             // new_inner_size is &mut so w have to dereference it twice
                             state.resize(**new_inner_size);
+                            debug_assert!(aspect_ratio(**new_inner_size)
+                                .map_or(true, |aspect| (state.camera.aspect - aspect).abs() < 1e-6));
                         }
                         _ => {}
                     }
@@ -668,26 +1581,242 @@ fn main() {
                 ref event,
                 ..
             } => {
+                // Mouse look only happens while the cursor is captured, which is never the case while paused
                 state.process_device_event(event);
             }
             Event::RedrawRequested(_) => {
-                state.update();
+                state.update(app_state);
+                // The FPS only changes once per second, so the title isn't rewritten every frame
+                let fps = state.fps().round() as u32;
+                if fps != shown_fps {
+                    window.set_title(&format!("mycraft - {} FPS", fps));
+                    shown_fps = fps;
+                }
                 match state.render() {
                     Ok(_) => {}
                     // Recreate the swap_chain if lost
-                    Err(wgpu::SwapChainError::Lost) => state.resize(state.size),
+                    Err(wgpu::SwapChainError::Lost) => {
+                        log::warn!("Swap chain lost after {}, recreating it", state.gpu_operation.get());
+                        state.resize(state.size);
+                    }
                     // The system is out of memory, we should probably quit
-                    Err(wgpu::SwapChainError::OutOfMemory) => *control_flow = ControlFlow::Exit,
+                    Err(wgpu::SwapChainError::OutOfMemory) => {
+                        log::error!("Out of memory after {}, quitting", state.gpu_operation.get());
+                        *control_flow = ControlFlow::Exit;
+                    }
                     // All other errors (Outdated, Timeout) should be resolved by the next frame
-                    Err(e) => eprintln!("{:?}", e),
+                    Err(e) => log::warn!("{:?} after {}", e, state.gpu_operation.get()),
                 }
             }
             Event::MainEventsCleared => {
                 // RedrawRequested will only trigger once, unless we manually
                 // request it.
+                state.wait_for_frame_cap();
                 window.request_redraw();
             }
             _ => {}
         }
     });
 }
+
+/// Number of pixels that differ by more than `tolerance` on any channel. `a` and `b` must have the same size.
+#[cfg(test)]
+fn count_mismatched_pixels(a: &image::RgbaImage, b: &image::RgbaImage, tolerance: u8) -> usize {
+    a.pixels()
+        .zip(b.pixels())
+        .filter(|(a, b)| a.0.iter().zip(b.0.iter()).any(|(a, b)| (*a as i16 - *b as i16).abs() > tolerance as i16))
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Renders the spawn view headlessly and compares it to tests/golden/cube.png.
+    /// Drivers don't rasterize exactly alike, so a few slightly different pixels are allowed.
+    /// When the golden image doesn't exist yet, the test writes it and skips the comparison.
+    /// Run with `MYCRAFT_UPDATE_GOLDEN=1` to rewrite it after an intended change, and review it before committing it.
+    #[test]
+    #[ignore] // Needs a GPU adapter: run with `cargo test -- --ignored`
+    fn headless_render_matches_the_golden_image() {
+        let golden_path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/cube.png");
+        let mut state = futures::executor::block_on(State::new_headless(
+            256,
+            256,
+            Spawn::default(),
+            AssetSource::Embedded,
+            &Settings::default(),
+        ))
+            .expect("Headless rendering needs a GPU adapter");
+        // Paused, so that the frame doesn't depend on how long the setup took
+        state.update(AppState::Paused);
+        let frame = state.capture_frame().unwrap();
+        if std::env::var_os("MYCRAFT_UPDATE_GOLDEN").is_some() || !golden_path.exists() {
+            std::fs::create_dir_all(golden_path.parent().unwrap()).unwrap();
+            frame.save(&golden_path).unwrap();
+            eprintln!(
+                "Wrote {}: nothing was compared. Check the picture and commit it.",
+                golden_path.display()
+            );
+            return;
+        }
+        let golden = image::open(&golden_path)
+            .unwrap_or_else(|err| panic!("Can't open {} ({})", golden_path.display(), err))
+            .to_rgba8();
+        assert_eq!(frame.dimensions(), golden.dimensions());
+        let mismatched = count_mismatched_pixels(&frame, &golden, 8);
+        assert!(
+            mismatched * 100 <= (frame.width() * frame.height()) as usize,
+            "{} pixels differ from {}",
+            mismatched,
+            golden_path.display()
+        );
+    }
+
+    #[test]
+    fn mismatched_pixels_are_counted_beyond_the_tolerance() {
+        let a = image::RgbaImage::from_pixel(4, 4, image::Rgba([100, 100, 100, 255]));
+        let mut b = a.clone();
+        b.put_pixel(0, 0, image::Rgba([108, 92, 100, 255]));
+        b.put_pixel(1, 0, image::Rgba([100, 100, 109, 255]));
+        b.put_pixel(2, 0, image::Rgba([100, 100, 100, 0]));
+        assert_eq!(count_mismatched_pixels(&a, &a, 0), 0);
+        assert_eq!(count_mismatched_pixels(&a, &b, 8), 2);
+        assert_eq!(count_mismatched_pixels(&a, &b, 255), 0);
+    }
+
+    /// Byte offset of each field of `Uniforms` but the padding, in declaration order
+    fn uniforms_field_offsets() -> Vec<(&'static str, usize)> {
+        let uniforms = Uniforms::new();
+        let base = &uniforms as *const Uniforms as usize;
+        macro_rules! offsets {
+            ($($field:ident),*) => {
+                vec![$((stringify!($field), &uniforms.$field as *const _ as usize - base)),*]
+            };
+        }
+        offsets!(
+            view_proj,
+            inv_view_proj,
+            view_position,
+            fog_start,
+            fog_color,
+            fog_end,
+            time,
+            ambient_shading,
+            ambient_strength,
+            diffuse_strength,
+            specular_strength,
+            shininess
+        )
+    }
+
+    #[test]
+    fn uniforms_match_the_layout_of_the_shaders() {
+        let shaders = [
+            ("shader.wgsl", include_str!("../assets/shader.wgsl")),
+            ("sky.wgsl", include_str!("../assets/sky.wgsl")),
+            ("line.wgsl", include_str!("../assets/line.wgsl")),
+        ];
+        for (name, source) in shaders.iter() {
+            let module = naga::front::wgsl::parse_str(source).unwrap_or_else(|err| panic!("{}: {}", name, err.emit_to_string()));
+            let (members, span) = module
+                .types
+                .iter()
+                .find_map(|(_, ty)| match &ty.inner {
+                    naga::TypeInner::Struct { members, span, .. } if ty.name.as_deref() == Some("Uniforms") => {
+                        Some((members, *span))
+                    }
+                    _ => None,
+                })
+                .unwrap_or_else(|| panic!("{} has no Uniforms struct", name));
+            let wgsl_offsets: Vec<_> = members
+                .iter()
+                .map(|member| (member.name.as_deref().unwrap_or(""), member.offset as usize))
+                .collect();
+            assert_eq!(wgsl_offsets, uniforms_field_offsets(), "in {}", name);
+            // WGSL rounds the size of the struct up to its alignment, which is 16 because of the matrices
+            let wgsl_size = (span as usize + 15) / 16 * 16;
+            assert_eq!(std::mem::size_of::<Uniforms>(), wgsl_size, "in {}", name);
+        }
+    }
+
+    #[test]
+    fn aspect_ratio_follows_the_physical_size_across_scale_changes() {
+        let logical_size = winit::dpi::LogicalSize::new(1001., 750.);
+        for &scale_factor in [1., 1.25, 1.5, 2.].iter() {
+            let physical_size: winit::dpi::PhysicalSize<u32> = logical_size.to_physical(scale_factor);
+            let aspect = aspect_ratio(physical_size).unwrap();
+            assert!((aspect - physical_size.width as f32 / physical_size.height as f32).abs() < 1e-6);
+            // Rounding to whole pixels barely changes it
+            assert!((aspect - 1001. / 750.).abs() < 1e-2, "x{}: {}", scale_factor, aspect);
+        }
+    }
+
+    #[test]
+    fn minimized_windows_have_no_aspect_ratio() {
+        assert_eq!(aspect_ratio(winit::dpi::PhysicalSize::new(0, 600)), None);
+        assert_eq!(aspect_ratio(winit::dpi::PhysicalSize::new(800, 0)), None);
+    }
+
+    #[test]
+    fn physics_waits_for_a_full_step() {
+        assert_eq!(physics_steps(PHYSICS_TIMESTEP / 2), (0, PHYSICS_TIMESTEP / 2));
+        assert_eq!(physics_steps(PHYSICS_TIMESTEP), (1, std::time::Duration::ZERO));
+    }
+
+    #[test]
+    fn physics_carries_the_remainder_over() {
+        let lag = PHYSICS_TIMESTEP * 5 / 2;
+        assert_eq!(physics_steps(lag), (2, lag - PHYSICS_TIMESTEP * 2));
+    }
+
+    #[test]
+    fn physics_catches_up_to_the_step_limit() {
+        let lag = PHYSICS_TIMESTEP * MAX_PHYSICS_STEPS_PER_FRAME + PHYSICS_TIMESTEP / 2;
+        assert_eq!(physics_steps(lag), (MAX_PHYSICS_STEPS_PER_FRAME, PHYSICS_TIMESTEP / 2));
+    }
+
+    #[test]
+    fn physics_drops_the_lag_of_a_hitch() {
+        let hitch = std::time::Duration::from_secs(10);
+        assert_eq!(physics_steps(hitch), (MAX_PHYSICS_STEPS_PER_FRAME, std::time::Duration::ZERO));
+    }
+
+    #[test]
+    fn a_hitch_moves_the_camera_at_most_max_step() {
+        let mut camera = Camera::new(1.);
+        let mut camera_controller = CameraController::new(12.);
+        // As fast as the controller allows
+        camera_controller.set_speed(f32::MAX);
+        camera_controller.set_sprinting(true);
+        camera_controller.move_axis = cgmath::Vector3::new(1., 1., 1.);
+        let start = camera.position();
+        let (steps, _) = physics_steps(std::time::Duration::from_secs(10));
+        for _ in 0..steps {
+            camera_controller.update_movement(&mut camera, PHYSICS_TIMESTEP);
+        }
+        assert!(camera_controller.limit_frame_movement(&mut camera, start));
+        let distance = cgmath::MetricSpace::distance(start, camera.position());
+        let max_step = Settings::default().max_step;
+        assert!(distance > max_step - 1e-3);
+        assert!(distance <= max_step + 1e-3, "moved {} in one frame, more than {}", distance, max_step);
+    }
+
+    #[test]
+    fn physics_step_count_does_not_depend_on_the_frame_rate() {
+        let simulate = |frame_duration: std::time::Duration, frames: u32| {
+            let mut lag = std::time::Duration::ZERO;
+            let mut total_steps = 0;
+            for _ in 0..frames {
+                let (steps, new_lag) = physics_steps(lag + frame_duration);
+                total_steps += steps;
+                lag = new_lag;
+            }
+            total_steps
+        };
+        // One second at 30, 60 and 120 FPS. PHYSICS_TIMESTEP is an even number of nanoseconds, so halving it is exact.
+        assert_eq!(simulate(PHYSICS_TIMESTEP * 2, 30), 60);
+        assert_eq!(simulate(PHYSICS_TIMESTEP, 60), 60);
+        assert_eq!(simulate(PHYSICS_TIMESTEP / 2, 120), 60);
+    }
+}
@@ -0,0 +1,122 @@
+use crate::Vertex;
+
+/// The six faces of a block, named after the direction their normal points to
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Face {
+    PosX,
+    NegX,
+    PosY,
+    NegY,
+    PosZ,
+    NegZ,
+}
+
+impl Face {
+    pub const ALL: [Face; 6] = [Face::PosX, Face::NegX, Face::PosY, Face::NegY, Face::PosZ, Face::NegZ];
+
+    pub fn normal(self) -> cgmath::Vector3<f32> {
+        match self {
+            Face::PosX => cgmath::Vector3::unit_x(),
+            Face::NegX => -cgmath::Vector3::unit_x(),
+            Face::PosY => cgmath::Vector3::unit_y(),
+            Face::NegY => -cgmath::Vector3::unit_y(),
+            Face::PosZ => cgmath::Vector3::unit_z(),
+            Face::NegZ => -cgmath::Vector3::unit_z(),
+        }
+    }
+
+    /// The right and up directions of the texture, as seen by someone looking at the face from outside.
+    /// right x up == normal, and side faces keep +Z as up so that their textures stand upright.
+    fn tangents(self) -> (cgmath::Vector3<f32>, cgmath::Vector3<f32>) {
+        use cgmath::Vector3;
+        match self {
+            Face::PosX => (Vector3::unit_y(), Vector3::unit_z()),
+            Face::NegX => (-Vector3::unit_y(), Vector3::unit_z()),
+            Face::PosY => (-Vector3::unit_x(), Vector3::unit_z()),
+            Face::NegY => (Vector3::unit_x(), Vector3::unit_z()),
+            Face::PosZ => (Vector3::unit_x(), Vector3::unit_y()),
+            Face::NegZ => (-Vector3::unit_x(), Vector3::unit_y()),
+        }
+    }
+}
+
+/// Indices of the two triangles of a quad returned by `face_vertices`.
+/// Offset them by 4 for each quad already in the mesh.
+pub const QUAD_INDICES: [u16; 6] = [0, 1, 2, 0, 2, 3];
+
+/// Vertices of one face of the unit block whose minimum corner is `block_pos`.
+/// They go bottom-left, bottom-right, top-right, top-left, which is counter-clockwise when seen from outside,
+/// so the quad survives `FrontFace::Ccw` + back-face culling exactly when we look at it from the side its normal points to.
+pub(crate) fn face_vertices(face: Face, block_pos: cgmath::Point3<i32>) -> [Vertex; 4] {
+    let (right, up) = face.tangents();
+    let center = block_pos.cast::<f32>().unwrap() + cgmath::Vector3::new(0.5, 0.5, 0.5) + face.normal() * 0.5;
+    let normal = face.normal().into();
+    let corner = |x: f32, y: f32, tex_coords: [f32; 2]| Vertex {
+        position: (center + right * (x * 0.5) + up * (y * 0.5)).into(),
+        tex_coords,
+        normal,
+    };
+    [
+        corner(-1., -1., [0., 1.]),
+        corner(1., -1., [1., 1.]),
+        corner(1., 1., [1., 0.]),
+        corner(-1., 1., [0., 0.]),
+    ]
+}
+
+/// The cube of the scene: the six faces of a block, scaled up to span from -1 to 1 on every axis
+pub(crate) fn cube_mesh() -> (Vec<Vertex>, Vec<u16>) {
+    let mut vertices = Vec::with_capacity(4 * Face::ALL.len());
+    let mut indices = Vec::with_capacity(QUAD_INDICES.len() * Face::ALL.len());
+    for &face in Face::ALL.iter() {
+        let offset = vertices.len() as u16;
+        indices.extend(QUAD_INDICES.iter().map(|index| index + offset));
+        vertices.extend(face_vertices(face, cgmath::Point3::new(0, 0, 0)).iter().map(|vertex| {
+            let [x, y, z] = vertex.position;
+            Vertex {
+                position: [x * 2. - 1., y * 2. - 1., z * 2. - 1.],
+                ..*vertex
+            }
+        }));
+    }
+    (vertices, indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::InnerSpace;
+
+    #[test]
+    fn faces_are_counter_clockwise_seen_from_outside() {
+        for &face in Face::ALL.iter() {
+            let vertices = face_vertices(face, cgmath::Point3::new(3, -2, 5));
+            for triangle in QUAD_INDICES.chunks(3) {
+                let [a, b, c] = [0, 1, 2].map(|i| cgmath::Vector3::from(vertices[triangle[i] as usize].position));
+                let winding_normal = (b - a).cross(c - a).normalize();
+                assert!(
+                    (winding_normal - face.normal()).magnitude() < 1e-5,
+                    "{:?}: the triangle {:?} faces {:?}",
+                    face,
+                    triangle,
+                    winding_normal
+                );
+            }
+            for vertex in vertices.iter() {
+                assert!((cgmath::Vector3::from(vertex.normal) - face.normal()).magnitude() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn faces_are_on_the_side_of_the_block_their_normal_points_to() {
+        let block_pos = cgmath::Point3::new(3, -2, 5);
+        let block_center = block_pos.cast::<f32>().unwrap() + cgmath::Vector3::new(0.5, 0.5, 0.5);
+        for &face in Face::ALL.iter() {
+            for vertex in face_vertices(face, block_pos).iter() {
+                let offset = cgmath::Point3::from(vertex.position) - block_center;
+                assert!((offset.dot(face.normal()) - 0.5).abs() < 1e-5, "{:?} is not on its side of the block", face);
+            }
+        }
+    }
+}
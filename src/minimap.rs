@@ -0,0 +1,334 @@
+use cgmath::Deg;
+use serde::{Deserialize, Serialize};
+use wgpu::util::DeviceExt;
+
+use crate::camera::{Camera, ProjectionKind, WORLD_UP};
+
+/// How high above the player the minimap looks down from. The camera far plane must reach past the ground below.
+const CAMERA_HEIGHT: f32 = 50.;
+/// Pixels between the minimap and the edges of the screen
+const MARGIN: u32 = 10;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MinimapCorner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// A top-down orthographic view of the surroundings, centered on the player and with +Y up.
+/// It is rendered into its own texture, then drawn in a corner of the screen with an arrow showing where the player faces.
+pub struct Minimap {
+    pub is_enabled: bool,
+    pub corner: MinimapCorner,
+    /// World units covered by each side of the map
+    pub extent: f32,
+    // Side of the map on screen, in pixels
+    size: u32,
+    format: wgpu::TextureFormat,
+    uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+    marker_buffer: wgpu::Buffer,
+    view: wgpu::TextureView,
+    multisampled_framebuffer: Option<wgpu::TextureView>,
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    bind_group: wgpu::BindGroup,
+}
+
+impl Minimap {
+    /// `uniform_bind_group_layout`, `light_buffer` and `sky_buffer` are the ones of the main view: the map is drawn with the same pipelines.
+    /// `sample_count` must be the one of those pipelines.
+    pub fn new(
+        device: &wgpu::Device,
+        uniform_bind_group_layout: &wgpu::BindGroupLayout,
+        light_buffer: &wgpu::Buffer,
+        sky_buffer: &wgpu::Buffer,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        asset_source: &crate::assets::AssetSource,
+    ) -> Self {
+        let size = 200;
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Minimap Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[crate::Uniforms::new()]),
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+        let uniform_bind_group = crate::render::create_uniform_bind_group(
+            device,
+            uniform_bind_group_layout,
+            &uniform_buffer,
+            light_buffer,
+            sky_buffer,
+        );
+        let marker_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Minimap Marker Buffer"),
+            // Facing direction, padded to the 16 bytes of a uniform struct
+            contents: bytemuck::cast_slice(&[0f32, 1., 0., 0.]),
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+
+        let shader = crate::render::create_wgsl_shader(device, "Minimap Shader", &asset_source.load_text("minimap.wgsl"));
+        let bind_group_layout = create_bind_group_layout(device);
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Minimap Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = create_minimap_pipeline(device, &pipeline_layout, &shader, format);
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Minimap Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        let view = create_map_view(device, format, size);
+        let bind_group = create_bind_group(device, &bind_group_layout, &view, &sampler, &marker_buffer);
+        let multisampled_framebuffer = create_multisampled_framebuffer(device, format, size, sample_count);
+
+        Self {
+            // A debug overlay: off unless the settings or F4 turn it on
+            is_enabled: false,
+            corner: MinimapCorner::TopRight,
+            extent: 32.,
+            size,
+            format,
+            uniform_buffer,
+            uniform_bind_group,
+            marker_buffer,
+            view,
+            multisampled_framebuffer,
+            pipeline,
+            bind_group_layout,
+            sampler,
+            bind_group,
+        }
+    }
+
+    /// `size` is the side of the map on screen, in pixels
+    pub fn set_size(&mut self, device: &wgpu::Device, size: u32, sample_count: u32) {
+        self.size = size.max(1);
+        self.view = create_map_view(device, self.format, self.size);
+        self.bind_group = create_bind_group(device, &self.bind_group_layout, &self.view, &self.sampler, &self.marker_buffer);
+        self.set_sample_count(device, sample_count);
+    }
+
+    /// Must be called whenever the sample count of the main pipelines changes
+    pub fn set_sample_count(&mut self, device: &wgpu::Device, sample_count: u32) {
+        self.multisampled_framebuffer = create_multisampled_framebuffer(device, self.format, self.size, sample_count);
+    }
+
+    /// `uniforms` are the ones of the main view, to get the same fog and time. Only the point of view, and the fog distances to match it, are replaced.
+    pub(crate) fn update(&self, queue: &wgpu::Queue, uniforms: &crate::Uniforms, player: &Camera) {
+        // Straight down is the one direction `look_at_rh` can't handle with a Z up vector, so we tilt by a hair.
+        // Facing +Y before tilting down is what keeps +Y at the top of the map.
+        let mut camera = Camera::with_transform(
+            1.,
+            player.position() + WORLD_UP * CAMERA_HEIGHT,
+            Deg(90.).into(),
+            Deg(-89.9).into(),
+        );
+        camera.projection = ProjectionKind::Orthographic { height: self.extent };
+        let mut uniforms = *uniforms;
+        uniforms.update_view_proj(&camera);
+        // Distances are measured from high above the player: push the fog back by as much,
+        // otherwise a short render distance would fog out the whole map
+        uniforms.set_fog(uniforms.fog_color, uniforms.fog_start + CAMERA_HEIGHT, uniforms.fog_end + CAMERA_HEIGHT);
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniforms]));
+        let facing = player.forward_direction();
+        queue.write_buffer(&self.marker_buffer, 0, bytemuck::cast_slice(&[facing.x, facing.y, 0., 0.]));
+    }
+
+    /// The attachment to render the map into, and the one to resolve it into with MSAA, like the main view's
+    pub fn render_targets(&self) -> (&wgpu::TextureView, Option<&wgpu::TextureView>) {
+        match &self.multisampled_framebuffer {
+            Some(multisampled_framebuffer) => (multisampled_framebuffer, Some(&self.view)),
+            None => (&self.view, None),
+        }
+    }
+
+    pub fn uniform_bind_group(&self) -> &wgpu::BindGroup {
+        &self.uniform_bind_group
+    }
+
+    /// Draws the map in its corner of `target`, which is `target_width` x `target_height` pixels
+    pub fn draw(&self, encoder: &mut wgpu::CommandEncoder, target: &wgpu::TextureView, target_width: u32, target_height: u32) {
+        // Shrinks with tiny windows instead of going out of the viewport
+        let size = self.size.min(target_width.saturating_sub(2 * MARGIN)).min(target_height.saturating_sub(2 * MARGIN));
+        if size == 0 {
+            return;
+        }
+        let x = match self.corner {
+            MinimapCorner::TopLeft | MinimapCorner::BottomLeft => MARGIN,
+            MinimapCorner::TopRight | MinimapCorner::BottomRight => target_width - size - MARGIN,
+        };
+        let y = match self.corner {
+            MinimapCorner::TopLeft | MinimapCorner::TopRight => MARGIN,
+            MinimapCorner::BottomLeft | MinimapCorner::BottomRight => target_height - size - MARGIN,
+        };
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Minimap"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_viewport(x as f32, y as f32, size as f32, size as f32, 0., 1.);
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+fn create_map_view(device: &wgpu::Device, format: wgpu::TextureFormat, size: u32) -> wgpu::TextureView {
+    device
+        .create_texture(&wgpu::TextureDescriptor {
+            label: Some("Minimap"),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::SAMPLED,
+        })
+        .create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+fn create_multisampled_framebuffer(
+    device: &wgpu::Device,
+    format: wgpu::TextureFormat,
+    size: u32,
+    sample_count: u32,
+) -> Option<wgpu::TextureView> {
+    if sample_count > 1 {
+        Some(crate::render::create_multisampled_framebuffer(device, format, size, size, sample_count))
+    } else {
+        None
+    }
+}
+
+/// Layout of the group holding the map texture (binding 0), its sampler (binding 1) and the marker (binding 2)
+fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::Sampler {
+                    comparison: false,
+                    filtering: true,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ],
+        label: Some("minimap_bind_group_layout"),
+    })
+}
+
+fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    view: &wgpu::TextureView,
+    sampler: &wgpu::Sampler,
+    marker_buffer: &wgpu::Buffer,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: marker_buffer.as_entire_binding(),
+            },
+        ],
+        label: Some("minimap_bind_group"),
+    })
+}
+
+fn create_minimap_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Minimap Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "main",
+            // The triangle is generated from the vertex index
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "main",
+            targets: &[wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState {
+                    color: wgpu::BlendComponent::REPLACE,
+                    alpha: wgpu::BlendComponent::REPLACE,
+                }),
+                write_mask: wgpu::ColorWrite::ALL,
+            }],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: None,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            clamp_depth: false,
+            conservative: false,
+        },
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState {
+            count: 1,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+    })
+}
@@ -0,0 +1,97 @@
+use std::path::Path;
+
+use anyhow::*;
+use serde::{Deserialize, Serialize};
+
+use crate::camera::FovMode;
+use crate::minimap::MinimapCorner;
+
+/// User tunables, loaded from a TOML file at startup.
+/// Fields missing from the file keep their default value.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Radians turned per pixel of mouse motion
+    pub mouse_sensitivity: f32,
+    pub invert_y: bool,
+    /// Seconds it takes for mouse motion to be applied, 0 turns instantly
+    pub look_smoothing: f32,
+    /// Units per second
+    pub camera_speed: f32,
+    /// Longest distance the camera can move in one frame, however long the frame took. A block is 2 units wide.
+    pub max_step: f32,
+    /// Field of view, in degrees
+    pub fov: f32,
+    /// "vertical" or "horizontal": which extent of the screen `fov` applies to.
+    /// Horizontal keeps the same side-to-side view whatever the shape of the window.
+    pub fov_mode: FovMode,
+    /// Degrees added to the field of view when moving at twice the normal speed, 0 disables it
+    pub speed_fov_boost: f32,
+    /// Distance of the near clip plane
+    pub near_plane: f32,
+    /// Distance of the far clip plane, where the fog hides everything
+    pub render_distance: f32,
+    /// Maximum frames per second, on top of vsync. 0 means uncapped.
+    pub frame_cap: f32,
+    /// Whether the minimap starts visible. F4 toggles it.
+    pub show_minimap: bool,
+    /// Side of the minimap, in pixels
+    pub minimap_size: u32,
+    /// "top_left", "top_right", "bottom_left" or "bottom_right"
+    pub minimap_corner: MinimapCorner,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            mouse_sensitivity: 0.001,
+            invert_y: false,
+            look_smoothing: 0.,
+            camera_speed: 12.,
+            max_step: 6.,
+            fov: 45.,
+            fov_mode: FovMode::Vertical,
+            speed_fov_boost: 8.,
+            near_plane: 0.1,
+            render_distance: 100.,
+            frame_cap: 0.,
+            show_minimap: false,
+            minimap_size: 200,
+            minimap_corner: MinimapCorner::TopRight,
+        }
+    }
+}
+
+impl Settings {
+    /// Never fails: if the file can't be read or parsed we log why and use the defaults.
+    /// When there is no file yet, the defaults are written to it so that users have something to edit.
+    pub fn load_or_create(path: &Path) -> Self {
+        if !path.exists() {
+            let settings = Self::default();
+            match settings.save(path) {
+                Ok(()) => log::info!("Wrote default settings to {}", path.display()),
+                Err(err) => log::warn!("{:#}", err),
+            }
+            return settings;
+        }
+        match Self::load(path) {
+            Ok(settings) => settings,
+            Err(err) => {
+                log::warn!("{:#}. Using the default settings", err);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read settings file {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("{} is not a valid settings file", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let text = toml::to_string_pretty(self).context("Failed to serialize the settings")?;
+        std::fs::write(path, text)
+            .with_context(|| format!("Failed to write settings file {}", path.display()))
+    }
+}
@@ -1,7 +1,10 @@
-use std::num::NonZeroU32;
+use std::num::{NonZeroU32, NonZeroU8};
+use std::path::Path;
 
 use anyhow::*;
-use image::GenericImageView;
+
+/// Used in place of textures that can't be loaded
+const MISSING_TEXTURE_COLOR: [u8; 4] = [255, 0, 255, 255];
 
 pub struct Texture {
     pub texture: wgpu::Texture,
@@ -10,34 +13,79 @@ pub struct Texture {
 }
 
 impl Texture {
+    /// Reads and decodes the image at runtime, with its mip chain, so that textures can be swapped without recompiling.
+    /// The error names the file, and says whether it couldn't be read or isn't a valid image.
+    pub fn from_path(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: &Path,
+        label: &str,
+        filter: wgpu::FilterMode,
+    ) -> Result<Self> {
+        let bytes = std::fs::read(path)
+            .with_context(|| format!("Failed to read texture file {}", path.display()))?;
+        Self::from_bytes(device, queue, &bytes, label, filter)
+            .with_context(|| format!("{} is not a valid image", path.display()))
+    }
+
+    /// The magenta placeholder to use when a texture can't be loaded, so that the problem is obvious on screen
+    pub fn missing(device: &wgpu::Device, queue: &wgpu::Queue, label: &str) -> Self {
+        Self::from_color(device, queue, MISSING_TEXTURE_COLOR, label)
+    }
+
+    /// A 1x1 texture of the given color
+    pub fn from_color(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        rgba: [u8; 4],
+        label: &str,
+    ) -> Self {
+        let img = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, image::Rgba(rgba)));
+        Self::from_image(device, queue, &img, Some(label), false, wgpu::FilterMode::Nearest)
+            .expect("A 1x1 RGBA image is always a valid texture")
+    }
+
+    /// Decodes the image and generates the full mip chain, so that distant surfaces don't shimmer.
     pub fn from_bytes(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         bytes: &[u8],
         label: &str,
+        filter: wgpu::FilterMode,
     ) -> Result<Self> {
         let img = image::load_from_memory(bytes)?;
-        Self::from_image(device, queue, &img, Some(label))
+        Self::from_image(device, queue, &img, Some(label), true, filter)
     }
 
+    /// `filter` is used when the texture is magnified: `Nearest` gives crisp pixels, `Linear` smooths them.
+    /// Any size and pixel format is accepted, as long as the image isn't empty and fits in the device's texture size limit.
     pub fn from_image(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         img: &image::DynamicImage,
         label: Option<&str>,
+        generate_mips: bool,
+        filter: wgpu::FilterMode,
     ) -> Result<Self> {
-        let rgba = img.as_rgba8().unwrap();
-        let dimensions = img.dimensions();
+        // Images without an alpha channel (e.g. most JPEGs), or with 16 bit channels, are converted
+        let rgba = img.to_rgba8();
+        let dimensions = rgba.dimensions();
+        check_dimensions(label.unwrap_or("unnamed texture"), dimensions, device.limits().max_texture_dimension_2d)?;
 
         let size = wgpu::Extent3d {
             width: dimensions.0,
             height: dimensions.1,
             depth_or_array_layers: 1,
         };
+        let mip_level_count = if generate_mips {
+            mip_level_count(dimensions.0, dimensions.1)
+        } else {
+            1
+        };
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label,
             size,
-            mip_level_count: 1,
+            mip_level_count,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format: wgpu::TextureFormat::Rgba8UnormSrgb,
@@ -50,7 +98,9 @@ impl Texture {
                 mip_level: 0,
                 origin: wgpu::Origin3d::ZERO,
             },
-            rgba,
+            &rgba,
+            // Rows are tightly packed. Unlike buffer to texture copies, write_texture doesn't need them
+            // padded to COPY_BYTES_PER_ROW_ALIGNMENT: wgpu re-aligns them itself, so any width works.
             wgpu::ImageDataLayout {
                 offset: 0,
                 bytes_per_row: NonZeroU32::new(4 * dimensions.0),
@@ -59,16 +109,34 @@ impl Texture {
             size,
         );
 
+        // The mip chain is downsampled on the CPU, each level from the original image to avoid accumulating blur
+        for mip_level in 1..mip_level_count {
+            let (width, height) = mip_size(dimensions.0, dimensions.1, mip_level);
+            let mip = img
+                .resize_exact(width, height, image::imageops::FilterType::Triangle)
+                .to_rgba8();
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &texture,
+                    mip_level,
+                    origin: wgpu::Origin3d::ZERO,
+                },
+                &mip,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(4 * width),
+                    rows_per_image: NonZeroU32::new(height),
+                },
+                wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            ..Default::default()
-        });
+        let sampler = create_sampler(device, filter, 1);
 
         Ok(Self {
             texture,
@@ -76,4 +144,95 @@ impl Texture {
             sampler,
         })
     }
+
+    /// Replaces the sampler. Bind groups that use this texture must be recreated afterwards.
+    /// `anisotropy` must be 1 (off), 2, 4, 8 or 16. It is only used with `Linear` filtering, and adapters that don't
+    /// support anisotropic filtering silently ignore it.
+    pub fn set_sampler(&mut self, device: &wgpu::Device, filter: wgpu::FilterMode, anisotropy: u8) {
+        self.sampler = create_sampler(device, filter, anisotropy);
+    }
+}
+
+// Both filter modes are allowed by a layout declaring `filtering: true`, so switching doesn't require a new layout
+fn create_sampler(device: &wgpu::Device, filter: wgpu::FilterMode, anisotropy: u8) -> wgpu::Sampler {
+    // Anisotropic filtering needs linear minification, and some backends (e.g. DX12) apply it to magnification too,
+    // which would blur nearest filtering
+    let anisotropy = if filter == wgpu::FilterMode::Linear { anisotropy } else { 1 };
+    device.create_sampler(&wgpu::SamplerDescriptor {
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter: filter,
+        min_filter: if anisotropy > 1 { wgpu::FilterMode::Linear } else { wgpu::FilterMode::Nearest },
+        mipmap_filter: wgpu::FilterMode::Linear,
+        // 1 means no anisotropy, which wgpu wants as None
+        anisotropy_clamp: NonZeroU8::new(anisotropy).filter(|&clamp| clamp.get() > 1),
+        ..Default::default()
+    })
+}
+
+fn check_dimensions(name: &str, (width, height): (u32, u32), max_dimension: u32) -> Result<()> {
+    if width == 0 || height == 0 {
+        bail!("Texture {} is empty ({}x{})", name, width, height);
+    }
+    if width > max_dimension || height > max_dimension {
+        bail!(
+            "Texture {} is too big ({}x{}), the GPU supports up to {}x{}",
+            name, width, height, max_dimension, max_dimension
+        );
+    }
+    Ok(())
+}
+
+/// Number of levels needed to go from `width`x`height` down to 1x1
+fn mip_level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+/// Size of `mip_level`: each level halves the previous one, rounding down, but never goes below 1 pixel
+fn mip_size(width: u32, height: u32, mip_level: u32) -> (u32, u32) {
+    ((width >> mip_level).max(1), (height >> mip_level).max(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mip_chains_end_at_one_pixel() {
+        for &(width, height, level_count) in [(1, 1, 1), (3, 5, 3), (17, 1, 5), (256, 256, 9), (640, 480, 10)].iter() {
+            assert_eq!(mip_level_count(width, height), level_count, "{}x{}", width, height);
+            assert_eq!(mip_size(width, height, level_count - 1), (1, 1), "{}x{}", width, height);
+        }
+    }
+
+    #[test]
+    fn odd_sizes_round_down() {
+        assert_eq!(mip_size(3, 5, 1), (1, 2));
+        assert_eq!(mip_size(3, 5, 2), (1, 1));
+        assert_eq!(mip_size(17, 1, 1), (8, 1));
+        assert_eq!(mip_size(17, 1, 4), (1, 1));
+    }
+
+    #[test]
+    fn empty_and_oversized_images_are_rejected() {
+        assert!(check_dimensions("test", (0, 5), 8192).is_err());
+        assert!(check_dimensions("test", (5, 0), 8192).is_err());
+        assert!(check_dimensions("test", (8193, 1), 8192).is_err());
+        assert!(check_dimensions("test", (17, 1), 8192).is_ok());
+        assert!(check_dimensions("test", (8192, 8192), 8192).is_ok());
+    }
+
+    #[test]
+    fn each_mip_level_halves_the_previous_one() {
+        for &(width, height) in [(1, 1), (3, 5), (17, 1), (640, 480), (8192, 3)].iter() {
+            let mut previous = mip_size(width, height, 0);
+            assert_eq!(previous, (width, height));
+            for mip_level in 1..mip_level_count(width, height) {
+                let size = mip_size(width, height, mip_level);
+                assert_eq!(size, ((previous.0 / 2).max(1), (previous.1 / 2).max(1)), "{}x{} level {}", width, height, mip_level);
+                previous = size;
+            }
+        }
+    }
 }
@@ -0,0 +1,80 @@
+use cgmath::Vector3;
+
+/// Real seconds it takes for a full day to go by
+pub const DAY_DURATION: f32 = 600.;
+
+/// How the sun and the sky look at a given time of day
+#[derive(Debug, Copy, Clone)]
+pub struct DayLighting {
+    /// Direction the sunlight travels in, i.e. pointing away from the sun
+    pub sun_direction: Vector3<f32>,
+    pub sun_color: [f32; 3],
+    pub sky_top_color: [f32; 3],
+    /// Also used for the fog, so that distant blocks blend into the horizon
+    pub sky_bottom_color: [f32; 3],
+}
+
+struct Keyframe {
+    // Sine of the sun elevation, -1 straight down, 1 straight up
+    sun_height: f32,
+    sun_color: [f32; 3],
+    sky_top_color: [f32; 3],
+    sky_bottom_color: [f32; 3],
+}
+
+// Sorted by sun height. Warm when the sun is on the horizon, bluish at midday, dark at night.
+const KEYFRAMES: [Keyframe; 3] = [
+    Keyframe {
+        sun_height: -0.2,
+        sun_color: [0.05, 0.07, 0.15],
+        sky_top_color: [0.01, 0.01, 0.05],
+        sky_bottom_color: [0.03, 0.04, 0.08],
+    },
+    Keyframe {
+        sun_height: 0.,
+        sun_color: [1.0, 0.55, 0.3],
+        sky_top_color: [0.25, 0.3, 0.55],
+        sky_bottom_color: [0.95, 0.55, 0.35],
+    },
+    Keyframe {
+        sun_height: 0.4,
+        sun_color: [0.9, 0.95, 1.0],
+        sky_top_color: [0.25, 0.45, 0.85],
+        sky_bottom_color: [0.75, 0.85, 0.95],
+    },
+];
+
+/// `time_of_day` goes from 0 to 1: 0 is midnight, 0.25 sunrise, 0.5 noon and 0.75 sunset
+pub fn lighting_at(time_of_day: f32) -> DayLighting {
+    use cgmath::InnerSpace;
+    // The sun rises in +X, goes over the top and sets in -X. It is slightly tilted towards +Y
+    // so that faces facing X and Y never get the exact same shading.
+    let angle = (time_of_day - 0.25) * std::f32::consts::TAU;
+    let towards_sun = Vector3::new(angle.cos(), 0.3, angle.sin()).normalize();
+
+    let height = towards_sun.z;
+    let (a, b) = match KEYFRAMES.iter().position(|keyframe| height < keyframe.sun_height) {
+        Some(0) => (&KEYFRAMES[0], &KEYFRAMES[0]),
+        Some(i) => (&KEYFRAMES[i - 1], &KEYFRAMES[i]),
+        None => (&KEYFRAMES[KEYFRAMES.len() - 1], &KEYFRAMES[KEYFRAMES.len() - 1]),
+    };
+    let t = if b.sun_height > a.sun_height {
+        (height - a.sun_height) / (b.sun_height - a.sun_height)
+    } else {
+        0.
+    };
+    DayLighting {
+        sun_direction: -towards_sun,
+        sun_color: mix(a.sun_color, b.sun_color, t),
+        sky_top_color: mix(a.sky_top_color, b.sky_top_color, t),
+        sky_bottom_color: mix(a.sky_bottom_color, b.sky_bottom_color, t),
+    }
+}
+
+fn mix(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+    [
+        a[0] + (b[0] - a[0]) * t,
+        a[1] + (b[1] - a[1]) * t,
+        a[2] + (b[2] - a[2]) * t,
+    ]
+}